@@ -1,5 +1,13 @@
 use serde::{Deserialize, Deserializer, Serialize};
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+
+use crate::html;
+use crate::links;
+use crate::slug::{SlugStyle, Slugger};
+use crate::style::{self, EmphasisStyle, HrStyle, InsertionStyle, LinkRunStyle};
 
 /// Represents the Chrome Accessibility Tree node structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,12 +45,15 @@ pub struct ChromeRole {
     pub value: RoleValueContent,
 }
 
-/// Role value can be either an integer (internalRole) or a string role name
+/// Role value can be either an integer (internalRole) or a string role
+/// name. Any other shape (null, objects) is kept as-is so an unexpected
+/// value doesn't fail parsing of the whole tree.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum RoleValueContent {
     Internal(i64),
     Named(String),
+    Other(serde_json::Value),
 }
 
 /// Role information (used in role field)
@@ -81,16 +92,25 @@ pub struct Name {
     pub value: String,
 }
 
+impl Name {
+    /// The text of the first non-superseded `title` attribute source
+    pub fn title_attribute(&self) -> Option<&str> {
+        self.sources.iter()
+            .filter(|source| source.superseded != Some(true))
+            .filter(|source| source.attribute.as_deref() == Some("title"))
+            .filter_map(|source| source.value.as_ref()?.value.as_str())
+            .find(|text| !text.trim().is_empty())
+    }
+}
+
 /// Source of a name
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NameSource {
-    #[serde(skip_deserializing)]
     pub attribute: Option<String>,
     #[serde(rename = "superseded")]
     pub superseded: Option<bool>,
     #[serde(rename = "type")]
     pub source_type: String,
-    #[serde(skip_deserializing)]
     pub value: Option<Value>,
 }
 
@@ -99,7 +119,9 @@ pub struct NameSource {
 pub struct Value {
     #[serde(rename = "type")]
     pub value_type: String,
-    pub value: String,
+    /// Usually a string, but CDP allows any JSON value here
+    #[serde(default)]
+    pub value: serde_json::Value,
 }
 
 /// Property of a node
@@ -126,7 +148,8 @@ impl Serialize for PropertyValue {
         let mut map = serializer.serialize_map(Some(2))?;
         map.serialize_entry("type", &self.value_type)?;
         match &self.value {
-            PropertyValueContent::Boolean(b) => map.serialize_entry("value", b)?,
+            // Written in CDP's shape so the output parses back into a tree
+            PropertyValueContent::Boolean(b) => map.serialize_entry("value", &b.value)?,
             PropertyValueContent::SimpleBoolean(b) => map.serialize_entry("value", b)?,
             PropertyValueContent::String(s) => map.serialize_entry("value", s)?,
             PropertyValueContent::Integer(i) => map.serialize_entry("value", i)?,
@@ -327,6 +350,42 @@ pub struct BooleanOrUndefined {
     pub value: bool,
 }
 
+/// A child ID that doesn't match any node in the tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingChild {
+    pub parent_id: String,
+    pub child_id: String,
+}
+
+impl std::fmt::Display for MissingChild {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "node {} references missing child {}", self.parent_id, self.child_id)
+    }
+}
+
+/// Why a tree has no content to convert, telling a failed capture apart
+/// from a page that is genuinely empty
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmptyTree {
+    /// The tree has no nodes at all, usually a failed capture
+    NoNodes,
+    /// Every node below the root is ignored, such as a blank page
+    OnlyIgnored,
+}
+
+impl std::fmt::Display for EmptyTree {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EmptyTree::NoNodes => write!(f, "accessibility tree has no nodes"),
+            EmptyTree::OnlyIgnored => write!(f, "accessibility tree has only ignored nodes"),
+        }
+    }
+}
+
+/// Ignored reasons Chrome reports for `aria-hidden` elements and their
+/// descendants
+const ARIA_HIDDEN_REASONS: [&str; 4] = ["hidden", "hiddenRoot", "ariaHiddenElement", "ariaHiddenSubtree"];
+
 impl AxTree {
     /// Build a parent-child relationship map from the nodes
     #[allow(dead_code)]
@@ -347,6 +406,16 @@ impl AxTree {
         })
     }
 
+    /// The nodes conversion starts from: the `RootWebArea`, or when there
+    /// is none (such as a tree captured from an iframe or a fragment)
+    /// every node without a parent, in document order
+    pub fn roots(&self) -> Vec<&AxNode> {
+        match self.find_root() {
+            Some(root) => vec![root],
+            None => self.nodes.iter().filter(|n| n.parent_id.is_none()).collect(),
+        }
+    }
+
     /// Get children of a node by parent ID
     #[allow(dead_code)]
     pub fn get_children(&self, parent_id: &str) -> Vec<&AxNode> {
@@ -356,7 +425,9 @@ impl AxTree {
             .collect()
     }
 
-    /// Check if a node should be ignored (uninteresting)
+    /// Check if a node should be ignored (uninteresting). Nodes marked
+    /// `hidden` or with a `presentation` role carry no content of their
+    /// own, but their children are still converted.
     #[allow(dead_code)]
     pub fn is_ignored(&self, node: &AxNode) -> bool {
         node.ignored
@@ -367,6 +438,62 @@ impl AxTree {
                     reasons.iter().any(|r| r.name == "uninteresting")
                 })
                 .unwrap_or(false)
+            || get_boolean_property(node, "hidden").unwrap_or(false)
+            || matches!(node.role.value, RoleValueContent::Named(ref v) if v == "presentation")
+    }
+
+    /// Check if a node is hidden from assistive technology with
+    /// `aria-hidden`, in which case its whole subtree should be skipped
+    pub fn is_aria_hidden(&self, node: &AxNode) -> bool {
+        node.ignored_reasons
+            .as_ref()
+            .map(|reasons| {
+                reasons.iter().any(|r| ARIA_HIDDEN_REASONS.contains(&r.name.as_str()) && r.value_type.value)
+            })
+            .unwrap_or(false)
+    }
+
+    /// Get the URLs of all image nodes in the tree
+    pub fn image_urls(&self) -> Vec<String> {
+        let mut urls: Vec<String> = Vec::new();
+        for node in &self.nodes {
+            if matches!(node.role.value, RoleValueContent::Named(ref v) if v == "image")
+                && let Some(url) = get_url(node)
+                && !urls.contains(&url)
+            {
+                urls.push(url);
+            }
+        }
+        urls
+    }
+
+    /// Find child IDs that reference nodes missing from the tree
+    pub fn missing_children(&self) -> Vec<MissingChild> {
+        let ids: std::collections::HashSet<&str> =
+            self.nodes.iter().map(|n| n.node_id.as_str()).collect();
+        let mut missing = Vec::new();
+        for node in &self.nodes {
+            for child_id in node.child_ids.as_deref().unwrap_or(&Vec::new()) {
+                if !ids.contains(child_id.as_str()) {
+                    missing.push(MissingChild {
+                        parent_id: node.node_id.clone(),
+                        child_id: child_id.clone(),
+                    });
+                }
+            }
+        }
+        missing
+    }
+
+    /// Check whether the tree has nothing to convert, and why
+    pub fn emptiness(&self) -> Option<EmptyTree> {
+        if self.nodes.is_empty() {
+            return Some(EmptyTree::NoNodes);
+        }
+        let root_id = self.find_root().map(|root| root.node_id.as_str());
+        let has_content = self.nodes.iter()
+            .any(|node| Some(node.node_id.as_str()) != root_id && !self.is_ignored(node));
+        (!has_content).then_some(EmptyTree::OnlyIgnored)
     }
 
     /// Find a node by ID
@@ -374,7 +501,134 @@ impl AxTree {
         self.nodes.iter().find(|n| n.node_id == node_id)
     }
 
-    /// Check if a role is an internal role (like StaticText with value 158)
+    /// Stitch several trees (such as frames or the pages of an article)
+    /// into one that converts in a single pass. Node IDs are prefixed with
+    /// the tree's position to keep them apart, and each tree's root
+    /// becomes a `document` under a new root. A node with
+    /// `separator_role` is placed between consecutive trees when given.
+    pub fn merge(trees: Vec<AxTree>, separator_role: Option<&str>) -> AxTree {
+        let mut root = AxNode {
+            backend_dom_node_id: None,
+            child_ids: Some(Vec::new()),
+            chrome_role: None,
+            ignored: false,
+            ignored_reasons: None,
+            node_id: "merged".to_string(),
+            parent_id: None,
+            role: Role {
+                role_type: "role".to_string(),
+                value: RoleValueContent::Named("RootWebArea".to_string()),
+            },
+            name: None,
+            properties: None,
+        };
+        let mut nodes = Vec::new();
+        let mut top_level = Vec::new();
+        for (i, tree) in trees.into_iter().enumerate() {
+            let Some(tree_root) = tree.find_root().map(|n| n.node_id.clone()) else {
+                continue;
+            };
+            if let Some(role) = separator_role
+                && !top_level.is_empty()
+            {
+                let mut separator = root.clone();
+                separator.node_id = format!("{}:separator", i);
+                separator.parent_id = Some(root.node_id.clone());
+                separator.child_ids = None;
+                separator.role.value = RoleValueContent::Named(role.to_string());
+                top_level.push(separator.node_id.clone());
+                nodes.push(separator);
+            }
+            let namespaced = |id: &str| format!("{}:{}", i, id);
+            for mut node in tree.nodes {
+                if node.node_id == tree_root {
+                    node.parent_id = Some(root.node_id.clone());
+                    node.role.value = RoleValueContent::Named("document".to_string());
+                    top_level.push(namespaced(&node.node_id));
+                } else {
+                    node.parent_id = node.parent_id.as_deref().map(namespaced);
+                }
+                node.node_id = namespaced(&node.node_id);
+                node.child_ids = node.child_ids.map(|ids| ids.iter().map(|id| namespaced(id)).collect());
+                nodes.push(node);
+            }
+        }
+        root.child_ids = Some(top_level);
+        nodes.insert(0, root);
+        AxTree { nodes }
+    }
+
+    /// Keep only the subtrees added since an earlier snapshot of the same
+    /// page, compared by node ID. The added subtrees are re-parented onto
+    /// the root in document order.
+    pub fn added_since(&self, before: &AxTree) -> AxTree {
+        let Some(root) = self.find_root() else {
+            return AxTree { nodes: Vec::new() };
+        };
+        let seen: HashSet<&str> = before.nodes.iter().map(|n| n.node_id.as_str()).collect();
+        let is_new = |node: &AxNode| !seen.contains(node.node_id.as_str());
+
+        // Walk in document order, stopping at the first new node on each
+        // path since everything below it is part of the same subtree
+        let mut subtree_roots = Vec::new();
+        let mut stack = vec![root];
+        while let Some(node) = stack.pop() {
+            if node.node_id != root.node_id && is_new(node) {
+                subtree_roots.push(node.node_id.clone());
+                continue;
+            }
+            for child_id in node.child_ids.iter().flatten().rev() {
+                if let Some(child) = self.find_node(child_id) {
+                    stack.push(child);
+                }
+            }
+        }
+
+        let mut new_root = root.clone();
+        new_root.child_ids = Some(subtree_roots.clone());
+        let mut nodes = vec![new_root];
+        for node in self.nodes.iter().filter(|n| n.node_id != root.node_id && is_new(n)) {
+            let mut node = node.clone();
+            if subtree_roots.contains(&node.node_id) {
+                node.parent_id = Some(root.node_id.clone());
+            }
+            nodes.push(node);
+        }
+        AxTree { nodes }
+    }
+
+    /// Remove the nodes backed by the given DOM nodes along with
+    /// everything below them, dropping them from their parents' children
+    pub fn without_dom_nodes(&self, backend_ids: &HashSet<i64>) -> AxTree {
+        let mut removed: HashSet<&str> = HashSet::new();
+        let mut stack: Vec<&AxNode> = self.nodes.iter()
+            .filter(|n| n.backend_dom_node_id.is_some_and(|id| backend_ids.contains(&id)))
+            .collect();
+        while let Some(node) = stack.pop() {
+            if !removed.insert(node.node_id.as_str()) {
+                continue;
+            }
+            for child_id in node.child_ids.iter().flatten() {
+                if let Some(child) = self.find_node(child_id) {
+                    stack.push(child);
+                }
+            }
+        }
+
+        let nodes = self.nodes.iter()
+            .filter(|n| !removed.contains(n.node_id.as_str()))
+            .map(|n| {
+                let mut node = n.clone();
+                if let Some(ref mut child_ids) = node.child_ids {
+                    child_ids.retain(|id| !removed.contains(id.as_str()));
+                }
+                node
+            })
+            .collect();
+        AxTree { nodes }
+    }
+
+    /// Check if a role is an internal role (like StaticText)
     #[allow(dead_code)]
     pub fn is_internal_role(&self, role: &Role) -> bool {
         matches!(role.value, RoleValueContent::Internal(_))
@@ -397,35 +651,550 @@ impl AxTree {
     }
 }
 
+/// Role name of text leaf nodes
+const STATIC_TEXT: &str = "StaticText";
+/// Role name of the per-line text boxes beneath StaticText nodes
+const INLINE_TEXT_BOX: &str = "InlineTextBox";
+
+/// Role name of the bullet or number pseudo-node some Chrome versions add
+/// to list items
+const LIST_MARKER: &str = "ListMarker";
+
+/// Internal role value Chrome uses for StaticText nodes
+pub const STATIC_TEXT_ROLE: i64 = 158;
+/// Internal role value Chrome uses for InlineTextBox nodes
+pub const INLINE_TEXT_BOX_ROLE: i64 = 101;
+
+/// Maps Chrome's internal (numeric) role values to role names.
+///
+/// The numbering can change between Chrome versions so it can be
+/// overridden to match the browser the tree was captured from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InternalRoleMap {
+    roles: HashMap<i64, String>,
+}
+
+impl Default for InternalRoleMap {
+    fn default() -> Self {
+        let mut roles = HashMap::new();
+        roles.insert(STATIC_TEXT_ROLE, STATIC_TEXT.to_string());
+        roles.insert(INLINE_TEXT_BOX_ROLE, INLINE_TEXT_BOX.to_string());
+        InternalRoleMap { roles }
+    }
+}
+
+impl InternalRoleMap {
+    /// Map an internal role value to a role name, replacing any existing
+    /// value mapped to the same name
+    pub fn insert(&mut self, value: i64, name: &str) {
+        self.roles.retain(|_, v| v != name);
+        self.roles.insert(value, name.to_string());
+    }
+
+    /// Get the role name for an internal role value
+    pub fn name(&self, value: i64) -> Option<&str> {
+        self.roles.get(&value).map(String::as_str)
+    }
+}
+
+/// Deepest nesting converted when `ConvertOptions::max_depth` isn't set.
+/// Real pages rarely nest more than a few dozen levels, and this stays
+/// within a 2 MiB thread stack (tokio's workers) even in debug builds.
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// Options controlling how the accessibility tree is converted
+#[derive(Debug, Clone, Default)]
+pub struct ConvertOptions {
+    /// Normalize emitted link URLs (lowercase host, strip default ports)
+    pub normalize_links: bool,
+    /// Data URIs keyed by image URL, substituted as the image link target
+    pub inline_images: HashMap<String, String>,
+    /// Number of columns in the page layout, used to reorder content
+    /// into reading order when geometry is available
+    pub columns: Option<usize>,
+    /// Layout bounds keyed by backend DOM node ID
+    pub geometry: HashMap<i64, BoundingBox>,
+    /// Render footer content without the `--- Footer ---` label
+    pub no_footer_marker: bool,
+    /// Names of Chrome's internal role values
+    pub internal_roles: InternalRoleMap,
+    /// Emit only the text content as a single paragraph, dropping all
+    /// markdown structure
+    pub flatten: bool,
+    /// Rewrite superscript links to same-page notes as markdown footnotes
+    pub footnotes: bool,
+    /// Render links inside navigation and banner landmarks as plain text
+    pub strip_nav_links: bool,
+    /// Characters used for horizontal rules
+    pub hr_style: HrStyle,
+    /// Markers used for italic and bold text
+    pub emphasis_style: EmphasisStyle,
+    /// Markup for inserted text
+    pub insertion_style: InsertionStyle,
+    /// Join bold, italic and inserted runs with the same style that are
+    /// only separated by whitespace into one run. Runs with nothing
+    /// between them are always joined.
+    pub merge_inline_runs: bool,
+    /// Follow each emitted block with a `<!-- role -->` comment
+    pub debug_roles: bool,
+    /// Backslash-escape markdown characters in text outside of code
+    pub escape_markdown: bool,
+    /// Re-encode `&`, `<` and `>` in text as HTML entities
+    pub raw_entities: bool,
+    /// Keep content hidden with `aria-hidden` instead of skipping it
+    pub include_aria_hidden: bool,
+    /// Layout for runs of adjacent sibling links
+    pub link_run_style: LinkRunStyle,
+    /// Render note, complementary and alert regions as GFM alert callouts
+    pub callouts: bool,
+    /// Start the document with a table of contents linking to each heading
+    pub toc: bool,
+    /// Deepest heading level listed in the table of contents, all levels
+    /// when `None`. The body still renders every heading.
+    pub toc_depth: Option<i64>,
+    /// Anchor algorithm used for table of contents links
+    pub slug_style: SlugStyle,
+    /// Render multi-line code as a fenced block, one line per text box,
+    /// keeping indentation instead of collapsing whitespace
+    pub prettify_whitespace: bool,
+    /// Drop paragraphs and list items with fewer characters than this,
+    /// such as stray icon labels. Links and headings are always kept
+    pub min_text_length: usize,
+    /// Stop at the last complete block that fits in this many bytes, so
+    /// the output never ends mid-sentence
+    pub max_bytes: Option<usize>,
+    /// Drop a link that points to the same URL as the sibling link right
+    /// before it, such as a thumbnail and title linking to one article,
+    /// keeping whichever has more text
+    pub dedupe_adjacent_links: bool,
+    /// Render a heading directly followed by a lower-level heading as a
+    /// title with an italic subtitle line beneath it
+    pub merge_subtitles: bool,
+    /// Deepest nesting of nodes to convert, `DEFAULT_MAX_DEPTH` when
+    /// `None`. Guards against deep trees overflowing the stack.
+    pub max_depth: Option<usize>,
+}
+
+/// Layout bounds of a DOM node in CSS pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
 /// Markdown conversion context
-struct ConvertContext {
+struct ConvertContext<'a> {
     /// Nodes that have been processed (to avoid cycles)
     visited: std::collections::HashSet<String>,
+    /// Options for this conversion
+    options: &'a ConvertOptions,
+    /// Footnote references and definitions found in the tree
+    footnotes: Footnotes,
+    /// Landmark roles enclosing the current node, innermost last
+    landmarks: Vec<String>,
+    /// Lists enclosing the current node, innermost last, holding the
+    /// number of the next item for ordered lists
+    lists: Vec<Option<usize>>,
+    /// Number of `convert_node` calls on the stack
+    nesting: usize,
+    /// Whether a subtree was cut off for being nested too deeply. Set by
+    /// text collection too, which only borrows the context.
+    truncated: Cell<bool>,
 }
 
-impl ConvertContext {
-    fn new() -> Self {
+impl<'a> ConvertContext<'a> {
+    fn new(options: &'a ConvertOptions) -> Self {
         ConvertContext {
             visited: std::collections::HashSet::new(),
+            options,
+            footnotes: Footnotes::default(),
+            landmarks: Vec::new(),
+            lists: Vec::new(),
+            nesting: 0,
+            truncated: Cell::new(false),
+        }
+    }
+
+    /// Deepest nesting the conversion descends to
+    fn max_depth(&self) -> usize {
+        self.options.max_depth.unwrap_or(DEFAULT_MAX_DEPTH)
+    }
+
+    /// Check whether a walk `nesting` levels deep may visit another node,
+    /// noting that content was cut off when it may not
+    fn descend(&self, nesting: usize) -> bool {
+        if nesting < self.max_depth() {
+            return true;
+        }
+        self.truncated.set(true);
+        false
+    }
+
+    /// Push the role onto the landmark stack if it is a landmark.
+    /// Returns true when pushed so the caller can pop it on exit.
+    fn enter_landmark(&mut self, role: Option<&str>) -> bool {
+        match role {
+            Some(role) if LANDMARK_ROLES.contains(&role) => {
+                self.landmarks.push(role.to_string());
+                true
+            }
+            _ => false,
         }
     }
+
+    /// The innermost landmark enclosing the current node
+    #[allow(dead_code)]
+    fn current_landmark(&self) -> Option<&str> {
+        self.landmarks.last().map(String::as_str)
+    }
+
+    /// Check if a node's subtree should be skipped as `aria-hidden`
+    fn is_hidden(&self, axtree: &AxTree, node: &AxNode) -> bool {
+        !self.options.include_aria_hidden && axtree.is_aria_hidden(node)
+    }
+
+    /// Check if a node is ignored, treating kept `aria-hidden` content as
+    /// visible
+    fn is_ignored(&self, axtree: &AxTree, node: &AxNode) -> bool {
+        axtree.is_ignored(node) && !(self.options.include_aria_hidden && axtree.is_aria_hidden(node))
+    }
+
+    /// Check if the current node is inside a landmark with the given role
+    fn in_landmark(&self, role: &str) -> bool {
+        self.landmarks.iter().any(|l| l == role)
+    }
+}
+
+/// Roles that mark the major regions of a page
+const LANDMARK_ROLES: [&str; 8] = [
+    "banner",
+    "complementary",
+    "contentinfo",
+    "form",
+    "main",
+    "navigation",
+    "region",
+    "search",
+];
+
+/// Footnote references and their definitions
+#[derive(Default)]
+struct Footnotes {
+    /// Footnote number keyed by the node ID of each reference link
+    references: HashMap<String, usize>,
+    /// Node IDs of the list items defining each footnote, in order
+    definitions: Vec<String>,
 }
 
 /// Convert an accessibility tree to markdown
+#[allow(dead_code)]
 pub fn axtree_to_markdown(axtree: &AxTree) -> String {
-    let mut ctx = ConvertContext::new();
-    let mut result = Vec::new();
+    axtree_to_markdown_with_options(axtree, &ConvertOptions::default())
+}
 
-    // Find root and start conversion
-    if let Some(root) = axtree.find_root() {
-        convert_node(axtree, root, &mut ctx, 0, &mut result);
-    }
+/// Convert an accessibility tree to markdown using the given options
+pub fn axtree_to_markdown_with_options(axtree: &AxTree, options: &ConvertOptions) -> String {
+    let mut result: Vec<String> = Vec::new();
+    convert_tree(axtree, options, &mut result);
 
     // Join with newlines and clean up multiple consecutive blank lines
     let output = result.join("\n");
     clean_whitespace(&output)
 }
 
+/// Convert an accessibility tree to markdown, writing lines to the sink
+/// as they are produced rather than accumulating the whole document.
+/// Use this to cap memory use on very large pages.
+pub fn axtree_to_markdown_writer<W: Write>(
+    axtree: &AxTree,
+    options: &ConvertOptions,
+    sink: W,
+) -> io::Result<()> {
+    let mut writer = CleanLineWriter::new(sink);
+    convert_tree(axtree, options, &mut writer);
+    writer.finish()
+}
+
+/// Convert the tree from the root, pushing output lines to the sink
+fn convert_tree(axtree: &AxTree, options: &ConvertOptions, result: &mut dyn LineSink) {
+    let mut budget;
+    let result: &mut dyn LineSink = match options.max_bytes {
+        Some(max_bytes) => {
+            budget = ByteBudget { sink: result, remaining: max_bytes, exhausted: false };
+            &mut budget
+        }
+        None => result,
+    };
+
+    // Find the roots and start conversion
+    let roots = axtree.roots();
+    if roots.is_empty() {
+        return;
+    }
+    if options.toc && !options.flatten {
+        // The contents come from a first pass so they list exactly the
+        // headings the body renders
+        let mut blocks: Vec<Block> = Vec::new();
+        convert_roots(axtree, &roots, options, &mut blocks);
+        push_table_of_contents(blocks, options, result);
+    }
+    convert_roots(axtree, &roots, options, result);
+}
+
+/// Convert each root in turn, followed by any footnote definitions
+fn convert_roots(axtree: &AxTree, roots: &[&AxNode], options: &ConvertOptions, result: &mut dyn LineSink) {
+    let mut ctx = ConvertContext::new(options);
+    if options.footnotes {
+        ctx.footnotes = collect_footnotes(axtree, roots);
+    }
+    if options.flatten {
+        let mut blocks = Vec::new();
+        for root in roots {
+            flatten_node(axtree, root, &ctx, &mut blocks, 0);
+        }
+        let text = blocks.join(" ");
+        result.push(text.clone());
+        result.block(Block::Paragraph { text });
+        return;
+    }
+    for root in roots {
+        convert_node(axtree, root, &mut ctx, 0, result);
+    }
+    push_footnote_definitions(axtree, &ctx, result);
+    if ctx.truncated.get() {
+        result.push(String::new());
+        result.push(format!("<!-- truncated: nested deeper than {} levels -->", ctx.max_depth()));
+    }
+}
+
+/// Push a nested list of links to the document's headings, followed by
+/// a blank line
+fn push_table_of_contents(blocks: Vec<Block>, options: &ConvertOptions, result: &mut dyn LineSink) {
+    // Subtitles merged into the heading before them come through as
+    // paragraphs, so they get no entry or anchor. Anchors are generated
+    // for every other heading so repeats are numbered the same way the
+    // renderer numbers them, even when some are not listed
+    let mut slugger = Slugger::new(options.slug_style);
+    let headings: Vec<(i64, String, String)> = headings(blocks).into_iter()
+        .map(|(level, text)| {
+            let anchor = slugger.slug(&text);
+            (level, text, anchor)
+        })
+        .filter(|(level, _, _)| options.toc_depth.is_none_or(|depth| *level <= depth))
+        .collect();
+
+    let Some(top) = headings.iter().map(|(level, _, _)| *level).min() else {
+        return;
+    };
+    for (level, text, anchor) in headings {
+        let indent = "  ".repeat((level - top) as usize);
+        result.push(format!("{}- [{}](#{})", indent, text, anchor));
+    }
+    result.push(String::new());
+}
+
+/// The level and text of each heading in the blocks, including those
+/// inside quotes, in document order
+fn headings(blocks: Vec<Block>) -> Vec<(i64, String)> {
+    let mut headings = Vec::new();
+    for block in blocks {
+        match block {
+            Block::Heading { level, text } => headings.push((level, text)),
+            Block::Quote { blocks, .. } => headings.extend(self::headings(blocks)),
+            _ => {}
+        }
+    }
+    headings
+}
+
+/// Destination for lines of converted output
+trait LineSink {
+    fn push(&mut self, line: String);
+
+    /// Describe the block whose lines were just pushed. Only sinks
+    /// building the AST keep it.
+    fn block(&mut self, _block: Block) {}
+
+    /// Receive the text of a list item, collected by the enclosing list
+    fn list_item(&mut self, _text: String) {}
+}
+
+impl LineSink for Vec<String> {
+    fn push(&mut self, line: String) {
+        Vec::push(self, line);
+    }
+}
+
+/// Builds the AST, ignoring the markdown lines
+impl LineSink for Vec<Block> {
+    fn push(&mut self, _line: String) {}
+
+    fn block(&mut self, block: Block) {
+        Vec::push(self, block);
+    }
+}
+
+/// Lines and blocks converted beneath a node that wraps them, such as a
+/// blockquote
+#[derive(Default)]
+struct Buffer {
+    lines: Vec<String>,
+    blocks: Vec<Block>,
+}
+
+impl LineSink for Buffer {
+    fn push(&mut self, line: String) {
+        self.lines.push(line);
+    }
+
+    fn block(&mut self, block: Block) {
+        self.blocks.push(block);
+    }
+}
+
+/// Passes a list's lines through while collecting its items' text
+struct ListItems<'a> {
+    sink: &'a mut dyn LineSink,
+    items: Vec<String>,
+}
+
+impl LineSink for ListItems<'_> {
+    fn push(&mut self, line: String) {
+        self.sink.push(line);
+    }
+
+    fn block(&mut self, block: Block) {
+        self.sink.block(block);
+    }
+
+    fn list_item(&mut self, text: String) {
+        self.items.push(text);
+    }
+}
+
+/// Passes lines to a sink until one doesn't fit in the remaining bytes,
+/// then drops it and everything after it. Fenced code, tables and quotes
+/// are pushed as one multi-line string and other lines are whole blocks
+/// (or list items), so the output always ends on a block boundary.
+struct ByteBudget<'a> {
+    sink: &'a mut dyn LineSink,
+    remaining: usize,
+    exhausted: bool,
+}
+
+impl LineSink for ByteBudget<'_> {
+    fn push(&mut self, line: String) {
+        // Count the newline joining it to the previous line
+        let size = line.len() + 1;
+        if self.exhausted || size > self.remaining {
+            self.exhausted = true;
+            return;
+        }
+        self.remaining -= size;
+        self.sink.push(line);
+    }
+
+    fn block(&mut self, block: Block) {
+        // Blocks follow their lines, so this block's lines were dropped
+        if !self.exhausted {
+            self.sink.block(block);
+        }
+    }
+}
+
+/// Writes lines to a sink while applying the same cleanup as
+/// `clean_whitespace`. Only the most recent line is held in memory so
+/// its trailing whitespace can be trimmed if it ends the document.
+struct CleanLineWriter<W: Write> {
+    sink: W,
+    /// The last non-blank line, not yet written
+    held: Option<String>,
+    /// Whether a blank line should be written before the next line
+    pending_blank: bool,
+    /// First write error, reported when finishing
+    error: Option<io::Error>,
+}
+
+impl<W: Write> CleanLineWriter<W> {
+    fn new(sink: W) -> Self {
+        CleanLineWriter { sink, held: None, pending_blank: false, error: None }
+    }
+
+    fn write(&mut self, s: &str) {
+        if self.error.is_none()
+            && let Err(e) = self.sink.write_all(s.as_bytes())
+        {
+            self.error = Some(e);
+        }
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        if let Some(last) = self.held.take() {
+            self.write(last.trim_end());
+        }
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => self.sink.flush(),
+        }
+    }
+}
+
+impl<W: Write> LineSink for CleanLineWriter<W> {
+    fn push(&mut self, line: String) {
+        for line in line.split('\n') {
+            if line.trim().is_empty() {
+                // Blank lines are dropped before any content
+                if self.held.is_some() {
+                    self.pending_blank = true;
+                }
+                continue;
+            }
+            if let Some(prev) = self.held.take() {
+                self.write(&prev);
+                self.write("\n");
+                if self.pending_blank {
+                    self.write("\n");
+                }
+            }
+            self.pending_blank = false;
+            self.held = Some(line.to_string());
+        }
+    }
+}
+
+/// A block of converted content, independent of markdown syntax
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Block {
+    Heading { level: i64, text: String },
+    Paragraph { text: String },
+    List { ordered: bool, items: Vec<String> },
+    Link { text: String, url: String },
+    Image { alt: String, url: String },
+    Code { text: String },
+    /// Rows of cell text, the first being the header
+    Table { rows: Vec<Vec<String>> },
+    /// A blockquote, or a callout of the given kind (e.g. `NOTE`)
+    Quote {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        kind: Option<String>,
+        blocks: Vec<Block>,
+    },
+    Footnote { number: usize, text: String },
+    Separator,
+}
+
+/// Convert an accessibility tree to a list of structural blocks. The
+/// blocks come from the same conversion as the markdown, so they follow
+/// the same options.
+pub fn axtree_to_ast(axtree: &AxTree, options: &ConvertOptions) -> Vec<Block> {
+    let mut blocks: Vec<Block> = Vec::new();
+    convert_tree(axtree, options, &mut blocks);
+    blocks
+}
+
 /// Clean up excessive whitespace
 fn clean_whitespace(s: &str) -> String {
     let mut result = String::new();
@@ -455,149 +1224,159 @@ fn convert_node(
     node: &AxNode,
     ctx: &mut ConvertContext,
     depth: usize,
-    result: &mut Vec<String>,
+    result: &mut dyn LineSink,
+) {
+    // Stop descending into pathologically deep trees. The cut is marked
+    // at the end of the output.
+    if !ctx.descend(ctx.nesting) {
+        return;
+    }
+    ctx.nesting += 1;
+    convert_node_body(axtree, node, ctx, depth, result);
+    ctx.nesting -= 1;
+}
+
+/// Convert a node once `convert_node` has checked its nesting
+fn convert_node_body(
+    axtree: &AxTree,
+    node: &AxNode,
+    ctx: &mut ConvertContext,
+    depth: usize,
+    result: &mut dyn LineSink,
 ) {
     // Prevent cycles
     if !ctx.visited.insert(node.node_id.clone()) {
         return;
     }
 
+    // Skip aria-hidden subtrees and list marker pseudo-nodes entirely
+    if ctx.is_hidden(axtree, node) || is_list_marker(node, &ctx.options.internal_roles) {
+        return;
+    }
+
     // Skip ignored nodes (but still process their children if they have any)
-    if axtree.is_ignored(node) && !node.child_ids.as_deref().map(|c| c.is_empty()).unwrap_or(true) {
-        for child_id in node.child_ids.as_deref().unwrap() {
-            if let Some(child) = axtree.find_node(child_id) {
-                convert_node(axtree, child, ctx, depth, result);
-            }
-        }
+    if ctx.is_ignored(axtree, node) && !node.child_ids.as_deref().map(|c| c.is_empty()).unwrap_or(true) {
+        convert_children(axtree, node, ctx, depth, result);
         return;
     }
 
     // Get the role as a named string or internal value
     let role_name = axtree.get_named_role_value(&node.role);
+    let role = role_name.as_deref().unwrap_or_default();
+
+    // Track the landmark the node is inside
+    let is_landmark = ctx.enter_landmark(role_name.as_deref());
 
     match role_name.as_deref() {
         Some("RootWebArea") | Some("document") => {
             // Process all children of document
-            for child_id in node.child_ids.as_deref().unwrap_or(&Vec::new()) {
-                if let Some(child) = axtree.find_node(child_id) {
-                    convert_node(axtree, child, ctx, depth, result);
-                }
-            }
+            convert_children(axtree, node, ctx, depth, result);
         }
 
-        Some("heading") => {
-            // Get heading level
-            let level = get_heading_level(node);
-            let header_char = if level <= 6 {
-                "#".repeat(level as usize)
-            } else {
-                "#".repeat(6)
-            };
-
-            let text = get_text_content(axtree, node);
-            if !text.is_empty() {
-                result.push(format!("{} {}", header_char, text));
-                result.push(String::new()); // Blank line after heading
-            }
+        Some("heading") => convert_heading(axtree, node, ctx, depth, result),
 
-            for child_id in node.child_ids.as_deref().unwrap_or(&Vec::new()) {
-                if let Some(child) = axtree.find_node(child_id) {
-                    convert_node(axtree, child, ctx, depth + 1, result);
-                }
-            }
+        Some("link") if ctx.footnotes.references.contains_key(&node.node_id) => {
+            // Rendered as a footnote marker in the enclosing text
         }
 
-        Some("link") => {
-            let text = get_text_content(axtree, node);
-            if let Some(url) = get_url(node) {
-                result.push(format!("[{}]({})", text, url));
-            } else if !text.is_empty() {
-                result.push(text);
-            }
+        Some("link") if is_citation(axtree, node, ctx) => {
+            // Rendered as a plain `[1]` marker in the enclosing text
+        }
 
-            for child_id in node.child_ids.as_deref().unwrap_or(&Vec::new()) {
-                if let Some(child) = axtree.find_node(child_id) {
-                    convert_node(axtree, child, ctx, depth + 1, result);
-                }
-            }
+        Some("listItem") if ctx.footnotes.definitions.contains(&node.node_id) => {
+            // Rendered with the footnote definitions at the end
         }
 
+        Some("link") => convert_link(axtree, node, ctx, depth, result),
+
         Some("button") => {
-            let text = get_text_content(axtree, node);
+            let text = get_text_content(axtree, node, ctx);
             if !text.is_empty() {
-                result.push(format!("[{}]({})", text, "button"));
+                push_block(ctx, result, role, format!("[{}]({})", text, "button"));
             }
 
-            for child_id in node.child_ids.as_deref().unwrap_or(&Vec::new()) {
-                if let Some(child) = axtree.find_node(child_id) {
-                    convert_node(axtree, child, ctx, depth + 1, result);
-                }
-            }
+            convert_children(axtree, node, ctx, depth + 1, result);
         }
 
         Some("list") => {
-            for child_id in node.child_ids.as_deref().unwrap_or(&Vec::new()) {
-                if let Some(child) = axtree.find_node(child_id) {
-                    convert_node(axtree, child, ctx, depth + 1, result);
-                }
+            let start = ordered_list_start(axtree, node, ctx);
+            ctx.lists.push(start);
+            let mut list = ListItems { sink: result, items: Vec::new() };
+            convert_children(axtree, node, ctx, depth + 1, &mut list);
+            let items = list.items;
+            ctx.lists.pop();
+            if !items.is_empty() {
+                result.block(Block::List { ordered: start.is_some(), items });
             }
         }
 
-        Some("listItem") => {
-            let text = get_text_content(axtree, node);
-            if !text.is_empty() {
-                result.push(format!("- {}", text));
-            }
+        Some("listItem") => convert_list_item(axtree, node, ctx, depth, result),
 
-            for child_id in node.child_ids.as_deref().unwrap_or(&Vec::new()) {
-                if let Some(child) = axtree.find_node(child_id) {
-                    convert_node(axtree, child, ctx, depth + 1, result);
-                }
-            }
+        Some("code") if !in_text_block(axtree, node) => convert_code(axtree, node, ctx, result),
+
+        Some("code") => {
+            // Rendered inline as part of the enclosing block's text
         }
 
         Some("paragraph") => {
-            let text = get_text_content(axtree, node);
-            if !text.is_empty() {
-                result.push(text);
+            let text = get_text_content(axtree, node, ctx);
+            if !is_noise(&text, ctx.options) {
+                push_block(ctx, result, role, text.clone());
+                result.block(Block::Paragraph { text });
                 result.push(String::new()); // Blank line after paragraph
             }
 
-            for child_id in node.child_ids.as_deref().unwrap_or(&Vec::new()) {
-                if let Some(child) = axtree.find_node(child_id) {
-                    convert_node(axtree, child, ctx, depth + 1, result);
-                }
+            convert_children(axtree, node, ctx, depth + 1, result);
+        }
+
+        Some("term") if is_definition_term(axtree, node) => {
+            // A term in a definition list (`<dt>`), in bold above its
+            // definitions
+            let text = get_text_content(axtree, node, ctx);
+            if !is_noise(&text, ctx.options) {
+                let bold = ctx.options.emphasis_style.bold();
+                push_block(ctx, result, role, format!("{}{}{}", bold, text, bold));
+                result.block(Block::Paragraph { text });
+            }
+            convert_children(axtree, node, ctx, depth + 1, result);
+        }
+
+        Some("definition") => {
+            // A definition (`<dd>`) in definition list syntax
+            let text = get_text_content(axtree, node, ctx);
+            if !is_noise(&text, ctx.options) {
+                push_block(ctx, result, role, format!(": {}", text));
+                result.block(Block::Paragraph { text });
+                result.push(String::new());
             }
+            convert_children(axtree, node, ctx, depth + 1, result);
         }
 
         Some("article") => {
             // Process article content
-            for child_id in node.child_ids.as_deref().unwrap_or(&Vec::new()) {
-                if let Some(child) = axtree.find_node(child_id) {
-                    convert_node(axtree, child, ctx, depth + 1, result);
-                }
-            }
+            convert_children(axtree, node, ctx, depth + 1, result);
         }
 
         Some("main") => {
             // Process main content
-            for child_id in node.child_ids.as_deref().unwrap_or(&Vec::new()) {
-                if let Some(child) = axtree.find_node(child_id) {
-                    convert_node(axtree, child, ctx, depth + 1, result);
-                }
-            }
+            convert_children(axtree, node, ctx, depth + 1, result);
+        }
+
+        Some("note") | Some("complementary") | Some("alert") if ctx.options.callouts => {
+            let label = node.name.as_ref().map(|name| name.value.as_str()).unwrap_or_default();
+            convert_quote(axtree, node, ctx, depth, result, callout_kind(role, label));
         }
 
+        Some("blockquote") => convert_quote(axtree, node, ctx, depth, result, None),
+
         Some("contentinfo") | Some("footer") => {
             // Process footer content but mark it
             result.push(String::new());
-            result.push("--- Footer ---".to_string());
-
-            for child_id in node.child_ids.as_deref().unwrap_or(&Vec::new()) {
-                if let Some(child) = axtree.find_node(child_id) {
-                    convert_node(axtree, child, ctx, depth + 1, result);
-                }
+            if !ctx.options.no_footer_marker {
+                push_block(ctx, result, role, "--- Footer ---".to_string());
             }
+
+            convert_children(axtree, node, ctx, depth + 1, result);
         }
 
         Some("separator") => {
@@ -605,238 +1384,1359 @@ fn convert_node(
             let role_level = get_role_level(node);
             if role_level == 1 || depth == 0 {
                 result.push(String::new());
-                result.push("---".to_string());
+                push_block(ctx, result, role, ctx.options.hr_style.rule().to_string());
+                result.block(Block::Separator);
             }
 
-            for child_id in node.child_ids.as_deref().unwrap_or(&Vec::new()) {
-                if let Some(child) = axtree.find_node(child_id) {
-                    convert_node(axtree, child, ctx, depth + 1, result);
-                }
-            }
+            convert_children(axtree, node, ctx, depth + 1, result);
         }
 
         Some("generic") => {
             // Generic containers - process children
-            for child_id in node.child_ids.as_deref().unwrap_or(&Vec::new()) {
-                if let Some(child) = axtree.find_node(child_id) {
-                    convert_node(axtree, child, ctx, depth + 1, result);
-                }
-            }
+            convert_children(axtree, node, ctx, depth + 1, result);
         }
 
         Some("none") => {
             // None roles - just process children
-            for child_id in node.child_ids.as_deref().unwrap_or(&Vec::new()) {
-                if let Some(child) = axtree.find_node(child_id) {
-                    convert_node(axtree, child, ctx, depth + 1, result);
-                }
-            }
+            convert_children(axtree, node, ctx, depth + 1, result);
         }
 
         Some("image") => {
-            let alt_text = get_alt_text(node);
-            if !alt_text.is_empty() {
-                result.push(format!("![{}]({})", alt_text, get_url(node).unwrap_or_default()));
+            if let Some(image) = image_markdown(node, ctx) {
+                push_block(ctx, result, role, image);
+                result.block(Block::Image { alt: get_alt_text(node), url: get_url(node).unwrap_or_default() });
             }
 
-            for child_id in node.child_ids.as_deref().unwrap_or(&Vec::new()) {
-                if let Some(child) = axtree.find_node(child_id) {
-                    convert_node(axtree, child, ctx, depth + 1, result);
-                }
+            convert_children(axtree, node, ctx, depth + 1, result);
+        }
+
+        Some("table") | Some("grid") | Some("treegrid") => {
+            let rows = table_rows(axtree, node, ctx);
+            if !rows.is_empty() {
+                result.push(String::new());
+                push_block(ctx, result, role, render_table(&rows).join("\n"));
+                result.block(Block::Table { rows });
+                result.push(String::new());
             }
         }
 
-        _ => {
-            // For other roles (including internal roles like StaticText, InlineTextBox), process children
-            // StaticText has internal value 158, InlineTextBox has 101
-            for child_id in node.child_ids.as_deref().unwrap_or(&Vec::new()) {
-                if let Some(child) = axtree.find_node(child_id) {
-                    convert_node(axtree, child, ctx, depth + 1, result);
-                }
+        Some("progressbar") | Some("meter") | Some("slider") => {
+            let text = range_text(axtree, node, ctx);
+            if !text.is_empty() {
+                push_block(ctx, result, role, text.clone());
+                result.block(Block::Paragraph { text });
             }
         }
+
+        _ => {
+            // For other roles (including internal roles like StaticText,
+            // InlineTextBox), process children
+            convert_children(axtree, node, ctx, depth + 1, result);
+        }
+    }
+
+    if is_landmark {
+        ctx.landmarks.pop();
     }
 }
 
-/// Get text content from a node (including StaticText children)
-fn get_text_content(axtree: &AxTree, node: &AxNode) -> String {
-    let mut text = String::new();
+/// Convert a heading, merging the subtitle after it when asked to
+fn convert_heading(
+    axtree: &AxTree,
+    node: &AxNode,
+    ctx: &mut ConvertContext,
+    depth: usize,
+    result: &mut dyn LineSink,
+) {
+    let level = get_heading_level(node);
+    let header_char = "#".repeat(level.clamp(1, 6) as usize);
 
-    // Check if this node has direct name/value (and is not just a container for StaticText children)
-    if let Some(ref name) = node.name {
-        if !name.value.is_empty() && !has_only_static_text_children(axtree, node) {
-            text.push_str(&name.value);
+    let text = get_text_content(axtree, node, ctx);
+    if !text.is_empty() {
+        let mut heading = format!("{} {}", header_char, text);
+        let mut subtitle = None;
+        if ctx.options.merge_subtitles
+            && let Some(node) = subtitle_of(axtree, node, level, ctx)
+        {
+            // The subtitle heading is used up here
+            ctx.visited.insert(node.node_id.clone());
+            let italic = ctx.options.emphasis_style.italic();
+            let text = get_text_content(axtree, node, ctx);
+            heading.push_str(&format!("\n{}{}{}", italic, text, italic));
+            subtitle = Some(text);
+        }
+        push_block(ctx, result, "heading", heading);
+        result.block(Block::Heading { level: level.min(6), text });
+        if let Some(text) = subtitle {
+            result.block(Block::Paragraph { text });
+        }
+        result.push(String::new()); // Blank line after heading
+        convert_children(axtree, node, ctx, depth + 1, result);
+    } else {
+        // Headings without text of their own, like a linked logo,
+        // take their content from their children
+        let mut lines: Vec<String> = Vec::new();
+        convert_children(axtree, node, ctx, depth + 1, &mut lines);
+        let content: Vec<&str> = lines.iter()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with("<!--"))
+            .collect();
+        if !content.is_empty() {
+            let text = content.join(" ");
+            push_block(ctx, result, "heading", format!("{} {}", header_char, text));
+            result.block(Block::Heading { level: level.min(6), text });
+            result.push(String::new());
         }
     }
+}
 
-    // Get text from StaticText children (Internal role with value 158)
-    for child_id in node.child_ids.as_deref().unwrap_or(&Vec::new()) {
-        if let Some(child) = axtree.find_node(child_id) {
-            // Check for StaticText role (internal value 158)
-            // Can be in chromeRole field OR in role field with type "internalRole"
-            let internal_val = child.chrome_role.as_ref()
-                .and_then(|cr| match &cr.value {
-                    RoleValueContent::Internal(v) => Some(*v),
-                    _ => None,
-                })
-                .or_else(|| axtree.get_internal_role_value(&child.role));
+/// Convert a link, as plain text inside navigation when stripping
+/// navigation links
+fn convert_link(
+    axtree: &AxTree,
+    node: &AxNode,
+    ctx: &mut ConvertContext,
+    depth: usize,
+    result: &mut dyn LineSink,
+) {
+    let mut text = get_text_content(axtree, node, ctx);
+    if text.is_empty()
+        && let Some(image) = linked_image(axtree, node, ctx)
+    {
+        text = image;
+    }
+    if ctx.options.strip_nav_links
+        && (ctx.in_landmark("navigation") || ctx.in_landmark("banner"))
+    {
+        // Render navigation links as plain text
+        if !text.is_empty() {
+            push_block(ctx, result, "link", text.clone());
+            result.block(Block::Paragraph { text });
+        }
+    } else if let Some(url) = get_url(node) {
+        let url = if ctx.options.normalize_links {
+            links::normalize_url(&url)
+        } else {
+            url
+        };
+        push_block(ctx, result, "link", format!("[{}]({})", text, url));
+        result.block(Block::Link { text, url });
+    } else if !text.is_empty() {
+        push_block(ctx, result, "link", text.clone());
+        result.block(Block::Paragraph { text });
+    }
 
-            let named_val = axtree.get_named_role_value(&child.role);
+    convert_children(axtree, node, ctx, depth + 1, result);
+}
 
-            if matches!(internal_val, Some(158)) || named_val.as_deref() == Some("StaticText") {
-                // StaticText - get the text from name
-                if let Some(ref name) = child.name {
-                    text.push_str(&name.value);
-                }
-            } else if matches!(internal_val, Some(101)) || named_val.as_deref() == Some("InlineTextBox") {
-                // InlineTextBox - just add the text directly
-                if let Some(ref name) = child.name {
-                    text.push_str(&name.value);
-                }
-            } else if !axtree.is_ignored(child) {
-                text.push_str(&get_text_content(axtree, child));
-            }
+/// Convert a list item with the bullet or number of the list it is in
+fn convert_list_item(
+    axtree: &AxTree,
+    node: &AxNode,
+    ctx: &mut ConvertContext,
+    depth: usize,
+    result: &mut dyn LineSink,
+) {
+    let mut lines: Vec<String> = Vec::new();
+    convert_children(axtree, node, ctx, depth + 1, &mut lines);
+    let mut lines: Vec<String> = lines.iter()
+        .flat_map(|line| line.split('\n'))
+        .map(String::from)
+        .collect();
+
+    // An item made of blocks, such as several paragraphs, starts
+    // with the first line of its first block
+    let text = if has_block_children(axtree, node) {
+        match lines.iter().position(|line| !line.trim().is_empty()) {
+            Some(first) => lines.drain(..=first).next_back().unwrap_or_default(),
+            None => String::new(),
+        }
+    } else {
+        get_text_content(axtree, node, ctx)
+    };
+
+    if is_noise(&text, ctx.options) {
+        if !lines.is_empty() {
+            result.push(lines.join("\n"));
         }
+        return;
     }
 
-    // Clean up whitespace - join words with single space
-    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+    let bullet = match ctx.lists.last_mut() {
+        Some(Some(number)) => {
+            *number += 1;
+            format!("{}.", *number - 1)
+        }
+        _ => "-".to_string(),
+    };
+    push_block(ctx, result, "listItem", format!("{} {}", bullet, text));
+    result.list_item(text);
+
+    // Continuation lines and nested lists are indented by the
+    // bullet's width so they stay inside the item, and are pushed
+    // together so a fence or table in them is never cut
+    let indent = " ".repeat(bullet.len() + 1);
+    let continued: Vec<String> = lines.into_iter()
+        .map(|line| match line.trim().is_empty() {
+            true => String::new(),
+            false => format!("{}{}", indent, line),
+        })
+        .collect();
+    if !continued.is_empty() {
+        result.push(continued.join("\n"));
+    }
 }
 
-/// Check if node has only StaticText children
-fn has_only_static_text_children(axtree: &AxTree, node: &AxNode) -> bool {
-    for child_id in node.child_ids.as_deref().unwrap_or(&Vec::new()) {
-        if let Some(child) = axtree.find_node(child_id) {
-            match &child.role.value {
-                RoleValueContent::Internal(val) if *val == 158 || *val == 101 => {}
-                RoleValueContent::Named(v) if v == "StaticText" || v == "InlineTextBox" => {}
-                _ => return false,
+/// Convert code outside of a paragraph, fenced when it spans several
+/// lines
+fn convert_code(axtree: &AxTree, node: &AxNode, ctx: &ConvertContext, result: &mut dyn LineSink) {
+    // Each text box is a rendered line when prettifying, otherwise
+    // preformatted text keeps its line breaks in the text itself
+    let lines = match code_lines(axtree, node, ctx, ctx.nesting) {
+        lines if ctx.options.prettify_whitespace && lines.len() > 1 => lines,
+        _ => raw_text(axtree, node, ctx, ctx.nesting)
+            .trim_matches('\n')
+            .lines()
+            .map(|line| line.trim_end().to_string())
+            .collect(),
+    };
+    if lines.len() > 1 {
+        let text = lines.join("\n");
+        push_block(ctx, result, "code", format!("```\n{}\n```", text));
+        result.block(Block::Code { text });
+        result.push(String::new());
+    } else {
+        let text = get_text_content(axtree, node, ctx);
+        if !text.is_empty() {
+            push_block(ctx, result, "code", inline_code(&text));
+            result.block(Block::Code { text });
+            result.push(String::new());
+        }
+    }
+}
+
+/// Convert a blockquote, or a callout region when `kind` is given.
+/// Nested quotes are already prefixed by the time they get here so each
+/// level adds one more `>`.
+fn convert_quote(
+    axtree: &AxTree,
+    node: &AxNode,
+    ctx: &mut ConvertContext,
+    depth: usize,
+    result: &mut dyn LineSink,
+    kind: Option<&str>,
+) {
+    let role = axtree.get_named_role_value(&node.role).unwrap_or_default();
+    let mut buffer = Buffer::default();
+    convert_children(axtree, node, ctx, depth + 1, &mut buffer);
+    let quoted = render_callout(kind, &buffer.lines);
+    if !quoted.is_empty() {
+        result.push(String::new());
+        push_block(ctx, result, &role, quoted.join("\n"));
+        result.block(Block::Quote { kind: kind.map(String::from), blocks: buffer.blocks });
+        result.push(String::new());
+    }
+}
+
+/// The text of a range widget with its current value and bounds. They
+/// usually have no text children so the accessible name is preferred.
+fn range_text(axtree: &AxTree, node: &AxNode, ctx: &ConvertContext) -> String {
+    let text = node.name.as_ref()
+        .map(|n| n.value.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .unwrap_or_else(|| get_text_content(axtree, node, ctx));
+    let Some(now) = get_integer_property(node, "valuenow") else {
+        return text;
+    };
+    let range = match (
+        get_integer_property(node, "valuemin"),
+        get_integer_property(node, "valuemax"),
+    ) {
+        (Some(min), Some(max)) => format!(" ({}–{})", min, max),
+        _ => String::new(),
+    };
+    if text.is_empty() {
+        format!("{}{}", now, range)
+    } else {
+        format!("{}: {}{}", text, now, range)
+    }
+}
+
+/// Get the cell text of each row of a table or grid, padded to the same
+/// number of columns. The header row comes first, and tables labelled
+/// by row headers instead of column headers get a blank one so the
+/// labels stay in the first column.
+fn table_rows(axtree: &AxTree, table: &AxNode, ctx: &ConvertContext) -> Vec<Vec<String>> {
+    let mut rows: Vec<TableRow> = Vec::new();
+    collect_table_rows(axtree, table, ctx, &mut rows, ctx.nesting);
+    if rows.is_empty() {
+        return Vec::new();
+    }
+
+    // Virtualized grids may render rows out of order, so trust their
+    // indices when every row has one
+    if rows.iter().all(|row| row.index.is_some()) {
+        rows.sort_by_key(|row| row.index);
+    }
+    // The first run of header rows goes first wherever it is in the tree
+    if let Some(start) = rows.iter().position(|row| row.header) {
+        let end = rows[start..].iter().position(|row| !row.header).map_or(rows.len(), |n| start + n);
+        rows[..end].rotate_right(end - start);
+    }
+    let has_column_headers = rows[0].header;
+    let rows: Vec<Vec<TableCell>> = rows.into_iter().map(place_cells).collect();
+
+    let has_row_headers = rows.iter().any(|row| row.iter().any(|cell| cell.role == "rowheader"));
+
+    let mut rows: Vec<Vec<String>> = rows.into_iter()
+        .map(|mut row| {
+            // Keep the row label in the first column
+            if let Some(i) = row.iter().position(|cell| cell.role == "rowheader") {
+                let label = row.remove(i);
+                row.insert(0, label);
             }
+            row.into_iter().map(|cell| cell.text).collect()
+        })
+        .collect();
+
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    if columns == 0 {
+        return Vec::new();
+    }
+    if has_row_headers && !has_column_headers {
+        rows.insert(0, vec![String::new(); columns]);
+    }
+    for row in &mut rows {
+        row.resize(columns, String::new());
+    }
+    rows
+}
+
+/// Render table rows as a GFM table. The first row is the header and is
+/// followed by the delimiter row. Pipes in cell text are escaped so they
+/// don't split the cell.
+fn render_table(rows: &[Vec<String>]) -> Vec<String> {
+    let columns = rows.first().map_or(0, Vec::len);
+    let mut lines = Vec::new();
+    for (i, row) in rows.iter().enumerate() {
+        let cells: Vec<String> = row.iter().map(|cell| cell.replace('|', "\\|")).collect();
+        lines.push(format!("| {} |", cells.join(" | ")));
+        if i == 0 {
+            lines.push(format!("|{}|", vec![" --- "; columns].join("|")));
         }
     }
-    true
+    lines
 }
 
-/// Get URL from a node's properties
-fn get_url(node: &AxNode) -> Option<String> {
-    if let Some(ref props) = node.properties {
-        for prop in props {
-            if prop.name == "url" {
-                if let PropertyValueContent::String(url) = &prop.value_type.value {
-                    return Some(url.clone());
+/// A row of a table or grid
+struct TableRow {
+    /// 1-based `rowindex` of the row, if known
+    index: Option<i64>,
+    /// Made of column headers or inside a header row group (`<thead>`)
+    header: bool,
+    cells: Vec<TableCell>,
+}
+
+/// Check if a row's cells are column headers, ignoring empty cells
+fn is_header_row(cells: &[TableCell]) -> bool {
+    cells.iter().any(|cell| cell.role == "columnheader")
+        && cells.iter().all(|cell| cell.role == "columnheader" || cell.text.is_empty())
+}
+
+/// A cell of a table or grid
+struct TableCell {
+    role: String,
+    text: String,
+    /// 1-based `colindex` of the cell, if known
+    column: Option<i64>,
+}
+
+/// Place cells in their columns using `colindex` where present, filling
+/// gaps left by sparse grids with empty cells. Cells without an index
+/// take the next free column after the cell before them.
+fn place_cells(row: TableRow) -> Vec<TableCell> {
+    if row.cells.iter().all(|cell| cell.column.is_none()) {
+        return row.cells;
+    }
+
+    let mut slots: Vec<Option<TableCell>> = Vec::new();
+    let mut next = 0;
+    for cell in row.cells {
+        let mut position = match cell.column {
+            Some(column) if column >= 1 => column as usize - 1,
+            _ => next,
+        };
+        while slots.get(position).is_some_and(Option::is_some) {
+            position += 1;
+        }
+        if slots.len() <= position {
+            slots.resize_with(position + 1, || None);
+        }
+        slots[position] = Some(cell);
+        next = position + 1;
+    }
+    slots.into_iter()
+        .map(|slot| slot.unwrap_or(TableCell { role: "cell".to_string(), text: String::new(), column: None }))
+        .collect()
+}
+
+/// Collect the cells of each row beneath a table or grid
+fn collect_table_rows(
+    axtree: &AxTree,
+    node: &AxNode,
+    ctx: &ConvertContext,
+    rows: &mut Vec<TableRow>,
+    nesting: usize,
+) {
+    if !ctx.descend(nesting) {
+        return;
+    }
+    for child in node.child_ids.as_deref().unwrap_or(&Vec::new())
+        .iter()
+        .filter_map(|child_id| axtree.find_node(child_id))
+    {
+        let role = axtree.get_named_role_value(&child.role);
+        match role.as_deref() {
+            Some("row") => {
+                let cells: Vec<(&AxNode, TableCell)> = child.child_ids.as_deref().unwrap_or(&Vec::new())
+                    .iter()
+                    .filter_map(|cell_id| axtree.find_node(cell_id))
+                    .filter_map(|cell| {
+                        let role = axtree.get_named_role_value(&cell.role)?;
+                        matches!(role.as_str(), "cell" | "gridcell" | "columnheader" | "rowheader").then(|| {
+                            let text = get_text_content(axtree, cell, ctx);
+                            let column = get_integer_property(cell, "colindex");
+                            (cell, TableCell { role, text, column })
+                        })
+                    })
+                    .collect();
+                if !cells.is_empty() {
+                    let index = get_integer_property(child, "rowindex")
+                        .or_else(|| get_integer_property(cells[0].0, "rowindex"));
+                    let cells: Vec<TableCell> = cells.into_iter().map(|(_, cell)| cell).collect();
+                    let header = is_header_row(&cells);
+                    rows.push(TableRow { index, header, cells });
                 }
             }
+            // A row group is the table header (`<thead>`) when it has a
+            // row of column headers
+            Some("rowgroup") => {
+                let mut group = Vec::new();
+                collect_table_rows(axtree, child, ctx, &mut group, nesting + 1);
+                let is_header_group = group.iter().any(|row| row.header);
+                for mut row in group {
+                    row.header |= is_header_group;
+                    rows.push(row);
+                }
+            }
+            // Nested tables are not part of this table
+            Some("table") | Some("grid") | Some("treegrid") => {}
+            _ => collect_table_rows(axtree, child, ctx, rows, nesting + 1),
         }
     }
-    None
 }
 
-/// Get alt text from an image node
-fn get_alt_text(node: &AxNode) -> String {
-    if let Some(ref props) = node.properties {
-        for prop in props {
-            if prop.name == "alt" {
-                if let PropertyValueContent::String(alt) = &prop.value_type.value {
-                    return alt.clone();
-                }
+/// Push a block of output, followed by a comment naming the role that
+/// produced it when debugging roles
+fn push_block(ctx: &ConvertContext, result: &mut dyn LineSink, role: &str, line: String) {
+    result.push(line);
+    if ctx.options.debug_roles {
+        result.push(format!("<!-- {} -->", role));
+    }
+}
+
+/// Convert the children of a node, in column reading order when a
+/// column hint and node geometry are available
+fn convert_children(
+    axtree: &AxTree,
+    node: &AxNode,
+    ctx: &mut ConvertContext,
+    depth: usize,
+    result: &mut dyn LineSink,
+) {
+    let children: Vec<&AxNode> = node.child_ids.as_deref().unwrap_or(&Vec::new())
+        .iter()
+        .filter_map(|child_id| axtree.find_node(child_id))
+        .collect();
+
+    let mut ordered = order_by_columns(axtree, children, ctx.options);
+    if ctx.options.dedupe_adjacent_links {
+        ordered = dedupe_adjacent_links(axtree, ordered, ctx);
+    }
+    let runs: Vec<&[&AxNode]> = ordered
+        .chunk_by(|a, b| is_run_link(axtree, a, ctx) && is_run_link(axtree, b, ctx))
+        .collect();
+    for run in runs {
+        match run {
+            [child] => convert_node(axtree, child, ctx, depth, result),
+            links => convert_link_run(axtree, links, ctx, depth, result),
+        }
+    }
+}
+
+/// GFM alert types
+const CALLOUT_KINDS: [&str; 5] = ["NOTE", "TIP", "IMPORTANT", "WARNING", "CAUTION"];
+
+/// Pick the GFM alert type for a callout region. A label like "Warning"
+/// or "Tip: ..." wins, otherwise notes and alerts map to `NOTE` and
+/// `WARNING`. Returns `None` for a plain blockquote.
+fn callout_kind(role: &str, label: &str) -> Option<&'static str> {
+    let first_word = label
+        .split(|c: char| !c.is_alphanumeric())
+        .find(|word| !word.is_empty())
+        .unwrap_or_default()
+        .to_uppercase();
+    if let Some(kind) = CALLOUT_KINDS.iter().find(|kind| **kind == first_word) {
+        return Some(kind);
+    }
+    match role {
+        "note" => Some("NOTE"),
+        "alert" => Some("WARNING"),
+        _ => None,
+    }
+}
+
+/// Quote converted lines as a callout or blockquote, collapsing blank
+/// lines
+fn render_callout(kind: Option<&str>, lines: &[String]) -> Vec<String> {
+    let mut quoted: Vec<String> = Vec::new();
+    for line in lines.iter().flat_map(|line| line.split('\n')) {
+        let line = line.trim_end();
+        if line.is_empty() {
+            if quoted.last().is_some_and(|last| last != ">") {
+                quoted.push(">".to_string());
             }
+        } else {
+            quoted.push(format!("> {}", line));
         }
     }
-    String::new()
+    while quoted.last().is_some_and(|last| last == ">") {
+        quoted.pop();
+    }
+    if quoted.is_empty() {
+        return quoted;
+    }
+    if let Some(kind) = kind {
+        quoted.insert(0, format!("> [!{}]", kind));
+    }
+    quoted
 }
 
-/// Get heading level from properties
-fn get_heading_level(node: &AxNode) -> i64 {
-    if let Some(ref props) = node.properties {
-        for prop in props {
-            if prop.name == "level" {
-                if let PropertyValueContent::Integer(level) = &prop.value_type.value {
-                    return *level as i64;
-                }
+/// Check if a node is a visible link that can be grouped with adjacent
+/// sibling links
+fn is_run_link(axtree: &AxTree, node: &AxNode, ctx: &ConvertContext) -> bool {
+    axtree.get_named_role_value(&node.role).as_deref() == Some("link")
+        && !ctx.is_ignored(axtree, node)
+        && !ctx.is_hidden(axtree, node)
+        && !ctx.footnotes.references.contains_key(&node.node_id)
+}
+
+/// Collapse adjacent sibling links to the same URL into the one with the
+/// most text, so a title wins over an image-only link
+fn dedupe_adjacent_links<'a>(axtree: &AxTree, nodes: Vec<&'a AxNode>, ctx: &ConvertContext) -> Vec<&'a AxNode> {
+    let text_length = |link: &AxNode| collect_text(axtree, link, ctx, false, ctx.nesting).trim().chars().count();
+    let mut kept: Vec<&AxNode> = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        if let Some(last) = kept.last_mut()
+            && is_run_link(axtree, node, ctx)
+            && is_run_link(axtree, last, ctx)
+            && get_url(node).is_some_and(|url| get_url(last) == Some(url))
+        {
+            if text_length(node) > text_length(last) {
+                *last = node;
             }
+            continue;
         }
+        kept.push(node);
     }
-    1 // Default to h1
+    kept
 }
 
-/// Get role level from properties (for separators)
-fn get_role_level(node: &AxNode) -> i64 {
-    if let Some(ref props) = node.properties {
-        for prop in props {
-            if prop.name == "level" {
-                if let PropertyValueContent::Integer(level) = &prop.value_type.value {
-                    return *level as i64;
-                }
+/// Render a run of sibling links (navigation, tag clouds) with
+/// separators so they don't run together
+fn convert_link_run(
+    axtree: &AxTree,
+    links: &[&AxNode],
+    ctx: &mut ConvertContext,
+    depth: usize,
+    result: &mut dyn LineSink,
+) {
+    let mut items = Vec::new();
+    for link in links {
+        let mut buffer = Buffer::default();
+        convert_node(axtree, link, ctx, depth, &mut buffer);
+        for block in buffer.blocks {
+            result.block(block);
+        }
+        let item = buffer.lines.iter()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<&str>>()
+            .join(" ");
+        if !item.is_empty() {
+            items.push(item);
+        }
+    }
+
+    match ctx.options.link_run_style {
+        LinkRunStyle::Inline if !items.is_empty() => result.push(items.join(" ")),
+        LinkRunStyle::Inline => {}
+        LinkRunStyle::Bullets => {
+            for item in items {
+                result.push(format!("- {}", item));
             }
         }
     }
-    0
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Stable-sort sibling nodes into column bands by x-coordinate so each
+/// column is emitted in full before the next. Nodes without geometry
+/// stay with the band of the sibling before them.
+fn order_by_columns<'a>(
+    axtree: &AxTree,
+    children: Vec<&'a AxNode>,
+    options: &ConvertOptions,
+) -> Vec<&'a AxNode> {
+    let columns = match options.columns {
+        Some(n) if n > 1 && !options.geometry.is_empty() => n,
+        _ => return children,
+    };
+    let page_width = options.geometry.values()
+        .map(|b| b.x + b.width)
+        .fold(0.0, f64::max);
+    if page_width <= 0.0 {
+        return children;
+    }
+    let band_width = page_width / columns as f64;
 
-    #[test]
-    fn test_parse_axtree() {
-        let json = r#"{
-            "nodes": [
-                {
-                    "nodeId": "1",
-                    "role": {"type": "role", "value": "RootWebArea"},
-                    "childIds": ["2"],
-                    "ignored": false
-                },
-                {
-                    "nodeId": "2",
-                    "parentId": "1",
-                    "role": {"type": "role", "value": "heading"},
-                    "name": {"type": "computedString", "value": "Test Heading"},
-                    "childIds": ["-1"],
-                    "properties": [{"name": "level", "value": {"type": "integer", "value": 2}}]
-                },
+    let mut band = 0;
+    let mut banded: Vec<(usize, &AxNode)> = Vec::with_capacity(children.len());
+    for child in children {
+        if let Some(x) = node_x(axtree, child, &options.geometry) {
+            band = ((x / band_width) as usize).min(columns - 1);
+        }
+        banded.push((band, child));
+    }
+    banded.sort_by_key(|(band, _)| *band);
+    banded.into_iter().map(|(_, child)| child).collect()
+}
+
+/// Get the left edge of a node, falling back to its first descendant
+/// that has geometry
+fn node_x(axtree: &AxTree, node: &AxNode, geometry: &HashMap<i64, BoundingBox>) -> Option<f64> {
+    descendants(axtree, node).find_map(|descendant| {
+        let id = descendant.backend_dom_node_id?;
+        geometry.get(&id).map(|bounds| bounds.x)
+    })
+}
+
+/// Find footnote references and definitions.
+///
+/// References are links to a fragment on the same page that are inside
+/// (or wrap) a superscript. Definitions are list items containing a
+/// backlink to a reference, i.e. a same-page link whose fragment
+/// mentions "ref" (`#fnref1`, `#cite_ref-1`). The accessibility tree
+/// doesn't expose element IDs so the nth distinct reference is paired
+/// with the nth definition.
+fn collect_footnotes<'a>(axtree: &'a AxTree, roots: &[&'a AxNode]) -> Footnotes {
+    let page_url = roots.first().and_then(|root| get_url(root));
+    let mut footnotes = Footnotes::default();
+    let mut fragments: Vec<String> = Vec::new();
+    let mut stack: Vec<(&AxNode, bool)> = roots.iter().rev().map(|root| (*root, false)).collect();
+
+    while let Some((node, in_superscript)) = stack.pop() {
+        let role = axtree.get_named_role_value(&node.role);
+        match role.as_deref() {
+            Some("link") => {
+                if let Some(fragment) = same_page_fragment(node, page_url.as_deref())
+                    && (in_superscript || has_child_role(axtree, node, "superscript"))
                 {
-                    "nodeId": "-1",
-                    "parentId": "2",
-                    "role": {"type": "internalRole", "value": 158},
-                    "name": {"type": "computedString", "value": "Test Heading"}
+                    let number = match fragments.iter().position(|f| *f == fragment) {
+                        Some(i) => i + 1,
+                        None => {
+                            fragments.push(fragment);
+                            fragments.len()
+                        }
+                    };
+                    footnotes.references.insert(node.node_id.clone(), number);
+                    continue;
                 }
-            ]
-        }"#;
+            }
+            Some("listItem") if !in_superscript && has_backlink(axtree, node, page_url.as_deref()) => {
+                footnotes.definitions.push(node.node_id.clone());
+                continue;
+            }
+            _ => {}
+        }
 
-        let tree: AxTree = serde_json::from_str(json).unwrap();
-        assert_eq!(tree.nodes.len(), 3);
-        assert!(tree.find_root().is_some());
+        let in_superscript = in_superscript || role.as_deref() == Some("superscript");
+        for child_id in node.child_ids.as_deref().unwrap_or(&Vec::new()).iter().rev() {
+            if let Some(child) = axtree.find_node(child_id) {
+                stack.push((child, in_superscript));
+            }
+        }
     }
 
-    #[test]
-    fn test_simple_heading() {
-        let json = r#"{
-            "nodes": [
-                {
-                    "nodeId": "1",
-                    "role": {"type": "role", "value": "RootWebArea"},
-                    "childIds": ["2"],
-                    "ignored": false
-                },
-                {
-                    "nodeId": "2",
-                    "parentId": "1",
-                    "role": {"type": "role", "value": "heading"},
-                    "name": {"type": "computedString", "value": "Hello World"},
+    footnotes
+}
+
+/// Get the fragment of a link that points elsewhere on the same page
+fn same_page_fragment(node: &AxNode, page_url: Option<&str>) -> Option<String> {
+    let url = get_url(node)?;
+    let (base, fragment) = url.split_once('#')?;
+    let same_page = base.is_empty() || page_url.is_none_or(|page| page.split('#').next() == Some(base));
+    (same_page && !fragment.is_empty()).then(|| fragment.to_string())
+}
+
+/// Check if a node contains a backlink to a footnote reference
+fn has_backlink(axtree: &AxTree, node: &AxNode, page_url: Option<&str>) -> bool {
+    descendants(axtree, node).skip(1).any(|descendant| {
+        axtree.get_named_role_value(&descendant.role).as_deref() == Some("link")
+            && same_page_fragment(descendant, page_url)
+                .is_some_and(|f| f.to_ascii_lowercase().contains("ref"))
+    })
+}
+
+/// Check if any direct child of a node has the given role
+fn has_child_role(axtree: &AxTree, node: &AxNode, role: &str) -> bool {
+    node.child_ids.as_deref().unwrap_or(&Vec::new()).iter()
+        .filter_map(|child_id| axtree.find_node(child_id))
+        .any(|child| axtree.get_named_role_value(&child.role).as_deref() == Some(role))
+}
+
+/// Append the definition of each referenced footnote
+fn push_footnote_definitions(axtree: &AxTree, ctx: &ConvertContext, result: &mut dyn LineSink) {
+    let count = ctx.footnotes.references.values().max().copied().unwrap_or(0);
+    let definitions = ctx.footnotes.definitions.iter()
+        .filter_map(|id| axtree.find_node(id))
+        .take(count);
+
+    let mut pushed_blank = false;
+    for (i, definition) in definitions.enumerate() {
+        let mut text = get_text_content(axtree, definition, ctx);
+        // Drop the trailing backlink (e.g. "↩")
+        if let Some(backlink) = last_link_text(axtree, definition, ctx, ctx.nesting)
+            && let Some(stripped) = text.strip_suffix(&backlink)
+        {
+            text = stripped.trim_end().to_string();
+        }
+        if !pushed_blank {
+            result.push(String::new());
+            pushed_blank = true;
+        }
+        result.push(format!("[^{}]: {}", i + 1, text));
+        result.block(Block::Footnote { number: i + 1, text });
+    }
+}
+
+/// Get the text of the last link beneath a node `nesting` levels deep
+fn last_link_text(axtree: &AxTree, node: &AxNode, ctx: &ConvertContext, nesting: usize) -> Option<String> {
+    if !ctx.descend(nesting) {
+        return None;
+    }
+    node.child_ids.as_deref().unwrap_or(&Vec::new()).iter().rev()
+        .filter_map(|child_id| axtree.find_node(child_id))
+        .find_map(|child| {
+            if axtree.get_named_role_value(&child.role).as_deref() == Some("link") {
+                Some(get_text_content(axtree, child, ctx))
+            } else {
+                last_link_text(axtree, child, ctx, nesting + 1)
+            }
+        })
+}
+
+/// Collect the text of each block in reading order. Nodes with direct
+/// text children are treated as a single block so inline content stays
+/// joined; containers are descended into.
+fn flatten_node(axtree: &AxTree, node: &AxNode, ctx: &ConvertContext, blocks: &mut Vec<String>, nesting: usize) {
+    if !ctx.descend(nesting) || ctx.is_hidden(axtree, node) || is_list_marker(node, &ctx.options.internal_roles) {
+        return;
+    }
+
+    let children: Vec<&AxNode> = node.child_ids.as_deref().unwrap_or(&Vec::new())
+        .iter()
+        .filter_map(|child_id| axtree.find_node(child_id))
+        .collect();
+
+    let has_text_child = children.iter().any(|child| {
+        let name = resolve_role_name(&child.role, &ctx.options.internal_roles);
+        matches!(name, Some(STATIC_TEXT) | Some(INLINE_TEXT_BOX))
+    });
+
+    if has_text_child && !ctx.is_ignored(axtree, node) {
+        let text = get_text_content(axtree, node, ctx);
+        if !text.is_empty() {
+            blocks.push(text);
+        }
+        return;
+    }
+
+    for child in children {
+        flatten_node(axtree, child, ctx, blocks, nesting + 1);
+    }
+}
+
+/// Get text content from a node (including StaticText children)
+fn get_text_content(axtree: &AxTree, node: &AxNode, ctx: &ConvertContext) -> String {
+    collect_text(axtree, node, ctx, ctx.options.escape_markdown, ctx.nesting)
+}
+
+/// Collect the text of a node `nesting` levels deep, escaping markdown
+/// characters when `escape` is set. Escaping stops at code so it renders
+/// verbatim.
+fn collect_text(axtree: &AxTree, node: &AxNode, ctx: &ConvertContext, escape: bool, nesting: usize) -> String {
+    // Clean up whitespace - join words with single space
+    let text = collect_spaced_text(axtree, node, ctx, escape, nesting);
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Collect the text of a node like `collect_text`, keeping its whitespace
+/// so a parent can tell whether a styled run starts or ends with a space
+fn collect_spaced_text(axtree: &AxTree, node: &AxNode, ctx: &ConvertContext, escape: bool, nesting: usize) -> String {
+    let mut text = String::new();
+    if !ctx.descend(nesting) {
+        return text;
+    }
+    let roles = &ctx.options.internal_roles;
+    let escape = escape && resolve_role_name(&node.role, roles) != Some("code");
+    let push_text = |text: &mut String, value: &str| {
+        let value = if ctx.options.raw_entities {
+            Cow::Owned(html::encode_entities(value))
+        } else {
+            Cow::Borrowed(value)
+        };
+        if escape {
+            text.push_str(&style::escape_markdown(&value));
+        } else {
+            text.push_str(&value);
+        }
+    };
+
+    // The end of the last styled run in `text` and its markers, so a
+    // following run with the same style can be merged into it
+    let mut last_run: Option<(usize, (&str, &str))> = None;
+
+    // Get text from StaticText and InlineTextBox children
+    for child_id in node.child_ids.as_deref().unwrap_or(&Vec::new()) {
+        if let Some(child) = axtree.find_node(child_id) {
+            // The internal role can be in the chromeRole field OR in the
+            // role field with type "internalRole"
+            let internal_name = child.chrome_role.as_ref()
+                .and_then(|cr| match &cr.value {
+                    RoleValueContent::Internal(v) => Some(*v),
+                    _ => None,
+                })
+                .or_else(|| axtree.get_internal_role_value(&child.role))
+                .and_then(|v| roles.name(v));
+
+            let named_val = axtree.get_named_role_value(&child.role);
+
+            if internal_name == Some(LIST_MARKER) || named_val.as_deref() == Some(LIST_MARKER) {
+                // ListMarker - skip the bullet, the list item adds its own
+            } else if internal_name == Some(STATIC_TEXT) || named_val.as_deref() == Some(STATIC_TEXT) {
+                // StaticText - get the text from name
+                if let Some(ref name) = child.name {
+                    push_text(&mut text, &name.value);
+                }
+            } else if internal_name == Some(INLINE_TEXT_BOX) || named_val.as_deref() == Some(INLINE_TEXT_BOX) {
+                // InlineTextBox - just add the text directly
+                if let Some(ref name) = child.name {
+                    push_text(&mut text, &name.value);
+                }
+            } else if named_val.as_deref() == Some("list")
+                && resolve_role_name(&node.role, roles) == Some("listItem")
+            {
+                // Nested list - its items are rendered on their own lines
+            } else if let Some(number) = ctx.footnotes.references.get(&child.node_id) {
+                text.push_str(&format!("[^{}]", number));
+            } else if !ctx.is_ignored(axtree, child) && !ctx.is_hidden(axtree, child) {
+                let spaced = collect_spaced_text(axtree, child, ctx, escape, nesting + 1);
+                let child_text = spaced.split_whitespace().collect::<Vec<&str>>().join(" ");
+                // Spaces at the edges of code or a styled run go outside
+                // its markers, which can't open or close next to one
+                let leading = if spaced.starts_with(char::is_whitespace) { " " } else { "" };
+                let trailing = if spaced.ends_with(char::is_whitespace) { " " } else { "" };
+                let child_role = resolve_role_name(&child.role, roles);
+                // Flattened output is only words, so emphasis is dropped.
+                // Defined terms (`<dfn>`) are italic like emphasis.
+                let emphasis = &ctx.options.emphasis_style;
+                let markers = match child_role {
+                    _ if ctx.options.flatten => None,
+                    Some("strong") => Some((emphasis.bold(), emphasis.bold())),
+                    Some("emphasis") | Some("term") => Some((emphasis.italic(), emphasis.italic())),
+                    Some("insertion") => Some(ctx.options.insertion_style.markers()),
+                    _ => None,
+                };
+                if child_role == Some("code")
+                    && resolve_role_name(&node.role, roles) != Some("code")
+                    && !child_text.is_empty()
+                {
+                    text.push_str(&format!("{}{}{}", leading, inline_code(&child_text), trailing));
+                } else if let Some((open, close)) = markers
+                    && !child_text.is_empty()
+                    && !has_ancestor_role(axtree, child, child_role, roles)
+                {
+                    text.push_str(leading);
+                    // Runs that touch always merge, since their markers
+                    // would run together (`*a**b*`)
+                    let between = |end: usize| &text[end..];
+                    match last_run {
+                        Some((end, last)) if last == (open, close)
+                            && (between(end).is_empty()
+                                || ctx.options.merge_inline_runs && between(end).trim().is_empty()) =>
+                        {
+                            // Reopen the previous run, keeping the space
+                            // between the two
+                            let gap = text.split_off(end);
+                            text.truncate(end - close.len());
+                            text.push_str(&gap);
+                            text.push_str(&child_text);
+                            text.push_str(close);
+                        }
+                        _ => text.push_str(&format!("{}{}{}", open, child_text, close)),
+                    }
+                    last_run = Some((text.len(), (open, close)));
+                    text.push_str(trailing);
+                } else {
+                    text.push_str(&spaced);
+                }
+            }
+        }
+    }
+
+    // Use the node's own name when its children have no text. Names are
+    // usually computed from that text, so using both repeats it.
+    if text.trim().is_empty()
+        && let Some(ref name) = node.name
+        && !name.value.is_empty()
+        && !has_only_static_text_children(axtree, node, roles)
+    {
+        text.clear();
+        push_text(&mut text, &name.value);
+    }
+
+    // Fall back to the title attribute for elements named only by it
+    if text.trim().is_empty()
+        && let Some(title) = node.name.as_ref().and_then(|name| name.title_attribute())
+    {
+        push_text(&mut text, title);
+    }
+
+    text
+}
+
+/// Check if any ancestor of a node has the given role, so nested bold or
+/// italic text isn't wrapped in markers twice
+fn has_ancestor_role(axtree: &AxTree, node: &AxNode, role: Option<&str>, roles: &InternalRoleMap) -> bool {
+    let mut parent_id = node.parent_id.as_deref();
+    while let Some(parent) = parent_id.and_then(|id| axtree.find_node(id)) {
+        if resolve_role_name(&parent.role, roles) == role {
+            return true;
+        }
+        parent_id = parent.parent_id.as_deref();
+    }
+    false
+}
+
+/// Get the name of a role, looking up internal role values in the map
+fn resolve_role_name<'a>(role: &'a Role, roles: &'a InternalRoleMap) -> Option<&'a str> {
+    match &role.value {
+        RoleValueContent::Internal(val) => roles.name(*val),
+        RoleValueContent::Named(v) => Some(v.as_str()),
+        RoleValueContent::Other(_) => None,
+    }
+}
+
+/// Check if a node is a list marker pseudo-node, named either by its role
+/// or its internal chromeRole
+fn is_list_marker(node: &AxNode, roles: &InternalRoleMap) -> bool {
+    let chrome_role = node.chrome_role.as_ref().and_then(|cr| match &cr.value {
+        RoleValueContent::Internal(v) => roles.name(*v),
+        _ => None,
+    });
+    resolve_role_name(&node.role, roles) == Some(LIST_MARKER) || chrome_role == Some(LIST_MARKER)
+}
+
+/// The nodes a node's child IDs point to, skipping missing ones
+fn child_nodes<'a>(axtree: &'a AxTree, node: &'a AxNode) -> impl Iterator<Item = &'a AxNode> {
+    node.child_ids.iter().flatten().filter_map(|id| axtree.find_node(id))
+}
+
+/// A node and everything below it in document order. Walks with an
+/// explicit stack so deep trees can't overflow it.
+fn descendants<'a>(axtree: &'a AxTree, node: &'a AxNode) -> impl Iterator<Item = &'a AxNode> {
+    let mut stack = vec![node];
+    std::iter::from_fn(move || {
+        let node = stack.pop()?;
+        let children: Vec<&AxNode> = child_nodes(axtree, node).collect();
+        stack.extend(children.into_iter().rev());
+        Some(node)
+    })
+}
+
+/// Text of a list item's marker, such as `1. ` or `• `
+fn list_marker_text(axtree: &AxTree, item: &AxNode, roles: &InternalRoleMap) -> Option<String> {
+    let marker = child_nodes(axtree, item).find(|child| is_list_marker(child, roles))?;
+    if let Some(ref name) = marker.name
+        && !name.value.is_empty()
+    {
+        return Some(name.value.clone());
+    }
+    let text: String = child_nodes(axtree, marker)
+        .filter_map(|child| child.name.as_ref().map(|name| name.value.as_str()))
+        .collect();
+    Some(text)
+}
+
+/// The number an ordered list starts at, or None for an unordered list.
+/// Chrome doesn't expose the list type, so it's read from the first
+/// item's marker: `3.` starts at 3, while lettered or roman markers like
+/// `a)` start at 1 since markdown only numbers lists.
+fn ordered_list_start(axtree: &AxTree, list: &AxNode, ctx: &ConvertContext) -> Option<usize> {
+    let roles = &ctx.options.internal_roles;
+    let first = child_nodes(axtree, list)
+        .find(|child| resolve_role_name(&child.role, roles) == Some("listItem"))?;
+    let marker = list_marker_text(axtree, first, roles)?;
+    let label = marker.trim().strip_suffix(['.', ')'])?;
+    if label.is_empty() || !label.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    Some(label.parse().unwrap_or(1))
+}
+
+/// Check if block text is empty or shorter than `--min-text-length`
+fn is_noise(text: &str, options: &ConvertOptions) -> bool {
+    let length = text.trim().chars().count();
+    length == 0 || length < options.min_text_length
+}
+
+/// Render the image inside an otherwise empty link, such as a logo, so
+/// it can be used as the link text. The image is marked visited so it
+/// isn't rendered again after the link.
+fn linked_image(axtree: &AxTree, link: &AxNode, ctx: &mut ConvertContext) -> Option<String> {
+    let image = link.child_ids.as_deref().unwrap_or(&Vec::new())
+        .iter()
+        .filter_map(|child_id| axtree.find_node(child_id))
+        .find(|child| axtree.get_named_role_value(&child.role).as_deref() == Some("image"))?;
+    let markdown = image_markdown(image, ctx)?;
+    ctx.visited.insert(image.node_id.clone());
+    Some(markdown)
+}
+
+/// Roles whose text is collected as a whole, including that of inline
+/// descendants like code
+const TEXT_BLOCK_ROLES: [&str; 11] = [
+    "paragraph", "heading", "listItem", "link", "button", "cell", "gridcell", "columnheader", "rowheader",
+    "term", "definition",
+];
+
+/// Check if a term is part of a definition list rather than an inline
+/// defined term (`<dfn>`), by having definitions next to it
+fn is_definition_term(axtree: &AxTree, term: &AxNode) -> bool {
+    term.parent_id.as_deref()
+        .and_then(|parent_id| axtree.find_node(parent_id))
+        .is_some_and(|parent| {
+            child_nodes(axtree, parent)
+                .any(|child| axtree.get_named_role_value(&child.role).as_deref() == Some("definition"))
+        })
+}
+
+/// Check if a link is a citation marker like `[1]` in or around a
+/// superscript within running text. Its text already reads as a clean
+/// reference, so the link itself isn't repeated after the paragraph.
+fn is_citation(axtree: &AxTree, node: &AxNode, ctx: &ConvertContext) -> bool {
+    let text = collect_text(axtree, node, ctx, false, ctx.nesting);
+    let numbered = text.trim()
+        .strip_prefix('[')
+        .and_then(|text| text.strip_suffix(']'))
+        .is_some_and(|number| !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()));
+    if !numbered {
+        return false;
+    }
+    let parent = node.parent_id.as_deref().and_then(|parent_id| axtree.find_node(parent_id));
+    match parent {
+        Some(parent) if axtree.get_named_role_value(&parent.role).as_deref() == Some("superscript") => {
+            in_text_block(axtree, parent)
+        }
+        _ => has_child_role(axtree, node, "superscript") && in_text_block(axtree, node),
+    }
+}
+
+/// Check if a node's text is already part of its parent's text. List
+/// items made of blocks render their children as blocks instead.
+fn in_text_block(axtree: &AxTree, node: &AxNode) -> bool {
+    let Some(parent) = node.parent_id.as_deref().and_then(|parent_id| axtree.find_node(parent_id)) else {
+        return false;
+    };
+    match axtree.get_named_role_value(&parent.role).as_deref() {
+        Some("listItem") => !has_block_children(axtree, parent),
+        Some(role) => TEXT_BLOCK_ROLES.contains(&role),
+        None => false,
+    }
+}
+
+/// Roles that always render as blocks of their own
+const BLOCK_ROLES: [&str; 4] = ["paragraph", "heading", "blockquote", "table"];
+
+/// Check if a list item holds blocks, such as paragraphs or multi-line
+/// code, rather than a single line of text
+fn has_block_children(axtree: &AxTree, item: &AxNode) -> bool {
+    child_nodes(axtree, item).any(|child| match axtree.get_named_role_value(&child.role).as_deref() {
+        Some("code") => has_line_break(axtree, child),
+        Some(role) => BLOCK_ROLES.contains(&role),
+        None => false,
+    })
+}
+
+/// Check if any text below a node spans several lines
+fn has_line_break(axtree: &AxTree, node: &AxNode) -> bool {
+    descendants(axtree, node).any(|descendant| {
+        descendant.name.as_ref().is_some_and(|name| name.value.contains('\n'))
+    })
+}
+
+/// Wrap text in backticks as inline code, using double backticks when
+/// the text contains one
+fn inline_code(text: &str) -> String {
+    if text.contains('`') {
+        format!("`` {} ``", text)
+    } else {
+        format!("`{}`", text)
+    }
+}
+
+/// Collect the text below a node without collapsing whitespace, so line
+/// breaks and indentation in preformatted text survive
+fn raw_text(axtree: &AxTree, node: &AxNode, ctx: &ConvertContext, nesting: usize) -> String {
+    let roles = &ctx.options.internal_roles;
+    let mut text = String::new();
+    if !ctx.descend(nesting) {
+        return text;
+    }
+    for child_id in node.child_ids.as_deref().unwrap_or(&Vec::new()) {
+        let Some(child) = axtree.find_node(child_id) else {
+            continue;
+        };
+        match resolve_role_name(&child.role, roles) {
+            Some(STATIC_TEXT) => {
+                if let Some(ref name) = child.name {
+                    text.push_str(&name.value);
+                }
+            }
+            // Text boxes repeat their StaticText parent line by line
+            Some(INLINE_TEXT_BOX) => {}
+            _ if ctx.is_hidden(axtree, child) => {}
+            _ => text.push_str(&raw_text(axtree, child, ctx, nesting + 1)),
+        }
+    }
+    text
+}
+
+/// Collect the lines of a code element from its InlineTextBox nodes,
+/// which Chrome creates per rendered line, keeping leading whitespace
+fn code_lines(axtree: &AxTree, node: &AxNode, ctx: &ConvertContext, nesting: usize) -> Vec<String> {
+    let roles = &ctx.options.internal_roles;
+    let mut lines = Vec::new();
+    if !ctx.descend(nesting) {
+        return lines;
+    }
+    for child_id in node.child_ids.as_deref().unwrap_or(&Vec::new()) {
+        let Some(child) = axtree.find_node(child_id) else {
+            continue;
+        };
+        if ctx.is_hidden(axtree, child) {
+            continue;
+        }
+        if resolve_role_name(&child.role, roles) == Some(INLINE_TEXT_BOX) {
+            if let Some(ref name) = child.name {
+                lines.push(name.value.trim_end().to_string());
+            }
+        } else {
+            lines.extend(code_lines(axtree, child, ctx, nesting + 1));
+        }
+    }
+    lines
+}
+
+/// Check if node has only StaticText children
+fn has_only_static_text_children(axtree: &AxTree, node: &AxNode, roles: &InternalRoleMap) -> bool {
+    for child_id in node.child_ids.as_deref().unwrap_or(&Vec::new()) {
+        if let Some(child) = axtree.find_node(child_id) {
+            let name = resolve_role_name(&child.role, roles);
+            if !matches!(name, Some(STATIC_TEXT) | Some(INLINE_TEXT_BOX)) {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Get URL from a node's properties
+fn get_url(node: &AxNode) -> Option<String> {
+    if let Some(ref props) = node.properties {
+        for prop in props {
+            if prop.name == "url"
+                && let PropertyValueContent::String(url) = &prop.value_type.value
+            {
+                return Some(url.clone());
+            }
+        }
+    }
+    None
+}
+
+/// Render an image node as markdown, using the inlined data URI when
+/// there is one. Images with neither alt text nor a URL are skipped.
+fn image_markdown(node: &AxNode, ctx: &ConvertContext) -> Option<String> {
+    let alt = get_alt_text(node);
+    let url = get_url(node).unwrap_or_default();
+    if alt.is_empty() && url.is_empty() {
+        return None;
+    }
+    let src = ctx.options.inline_images.get(&url).unwrap_or(&url);
+    Some(format!("![{}]({})", alt, src))
+}
+
+/// Get alt text from an image node, falling back to its accessible name
+fn get_alt_text(node: &AxNode) -> String {
+    if let Some(ref props) = node.properties {
+        for prop in props {
+            if prop.name == "alt"
+                && let PropertyValueContent::String(alt) = &prop.value_type.value
+            {
+                return alt.clone();
+            }
+        }
+    }
+    node.name.as_ref().map(|name| name.value.clone()).unwrap_or_default()
+}
+
+/// The next visible sibling of a heading of the given level, when it is
+/// a heading of a lower level (e.g. an `h2` after an `h1`) with text
+fn subtitle_of<'a>(axtree: &'a AxTree, heading: &AxNode, level: i64, ctx: &ConvertContext) -> Option<&'a AxNode> {
+    let parent = axtree.find_node(heading.parent_id.as_deref()?)?;
+    let next = parent.child_ids.as_deref()?
+        .iter()
+        .skip_while(|id| **id != heading.node_id)
+        .skip(1)
+        .filter_map(|id| axtree.find_node(id))
+        .find(|sibling| !ctx.is_hidden(axtree, sibling) && !ctx.is_ignored(axtree, sibling))?;
+    let is_subtitle = axtree.get_named_role_value(&next.role).as_deref() == Some("heading")
+        && get_heading_level(next) > level
+        && !get_text_content(axtree, next, ctx).is_empty();
+    is_subtitle.then_some(next)
+}
+
+/// Get heading level from properties
+fn get_heading_level(node: &AxNode) -> i64 {
+    if let Some(ref props) = node.properties {
+        for prop in props {
+            if prop.name == "level"
+                && let PropertyValueContent::Integer(level) = &prop.value_type.value
+            {
+                return *level;
+            }
+        }
+    }
+    1 // Default to h1
+}
+
+/// Get an integer property by name (e.g. valuenow for range widgets).
+/// Chrome reports range values with the "number" type, which are
+/// rounded to the nearest integer.
+fn get_integer_property(node: &AxNode, name: &str) -> Option<i64> {
+    if let Some(ref props) = node.properties {
+        for prop in props {
+            if prop.name == name {
+                match &prop.value_type.value {
+                    PropertyValueContent::Integer(value) => return Some(*value),
+                    PropertyValueContent::Unknown(value) => {
+                        if let Some(number) = value.get("value").and_then(|v| v.as_f64()) {
+                            return Some(number.round() as i64);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Get a boolean property by name (e.g. hidden)
+fn get_boolean_property(node: &AxNode, name: &str) -> Option<bool> {
+    if let Some(ref props) = node.properties {
+        for prop in props {
+            if prop.name == name {
+                match &prop.value_type.value {
+                    PropertyValueContent::SimpleBoolean(value) => return Some(*value),
+                    PropertyValueContent::Boolean(value) => return Some(value.value),
+                    _ => {}
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Get role level from properties (for separators)
+fn get_role_level(node: &AxNode) -> i64 {
+    if let Some(ref props) = node.properties {
+        for prop in props {
+            if prop.name == "level"
+                && let PropertyValueContent::Integer(level) = &prop.value_type.value
+            {
+                return *level;
+            }
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::AxTreeBuilder;
+
+    #[test]
+    fn test_parse_axtree() {
+        let json = r#"{
+            "nodes": [
+                {
+                    "nodeId": "1",
+                    "role": {"type": "role", "value": "RootWebArea"},
+                    "childIds": ["2"],
+                    "ignored": false
+                },
+                {
+                    "nodeId": "2",
+                    "parentId": "1",
+                    "role": {"type": "role", "value": "heading"},
+                    "name": {"type": "computedString", "value": "Test Heading"},
                     "childIds": ["-1"],
-                    "properties": [{"name": "level", "value": {"type": "integer", "value": 1}}]
+                    "properties": [{"name": "level", "value": {"type": "integer", "value": 2}}]
                 },
                 {
                     "nodeId": "-1",
                     "parentId": "2",
                     "role": {"type": "internalRole", "value": 158},
-                    "name": {"type": "computedString", "value": "Hello World"}
+                    "name": {"type": "computedString", "value": "Test Heading"}
                 }
             ]
         }"#;
 
         let tree: AxTree = serde_json::from_str(json).unwrap();
+        assert_eq!(tree.nodes.len(), 3);
+        assert!(tree.find_root().is_some());
+    }
+
+    #[test]
+    fn test_simple_heading() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "heading", "1").name("Hello World").int_prop("level", 1)
+            .text("-1", "Hello World", "2")
+            .build();
+
         let md = axtree_to_markdown(&tree);
         assert!(md.contains("# Hello World"));
     }
@@ -874,7 +2774,7 @@ mod tests {
     }
 
     #[test]
-    fn test_paragraph_conversion() {
+    fn test_normalize_links_option() {
         let json = r#"{
             "nodes": [
                 {
@@ -886,9 +2786,215 @@ mod tests {
                 {
                     "nodeId": "2",
                     "parentId": "1",
-                    "role": {"type": "role", "value": "paragraph"},
-                    "name": {"type": "computedString", "value": ""},
-                    "childIds": ["-1"]
+                    "role": {"type": "role", "value": "link"},
+                    "name": {"type": "computedString", "value": "Click me"},
+                    "childIds": ["-1"],
+                    "properties": [{"name": "url", "value": {"type": "string", "value": "HTTP://Example.COM:80/a/../b"}}]
+                },
+                {
+                    "nodeId": "-1",
+                    "parentId": "2",
+                    "role": {"type": "internalRole", "value": 158},
+                    "name": {"type": "computedString", "value": "Click me"}
+                }
+            ]
+        }"#;
+
+        let tree: AxTree = serde_json::from_str(json).unwrap();
+        let options = ConvertOptions { normalize_links: true, ..Default::default() };
+        let md = axtree_to_markdown_with_options(&tree, &options);
+        assert!(md.contains("[Click me](http://example.com/b)"));
+    }
+
+    #[test]
+    fn test_inline_images() {
+        let json = r#"{
+            "nodes": [
+                {
+                    "nodeId": "1",
+                    "role": {"type": "role", "value": "RootWebArea"},
+                    "childIds": ["2"],
+                    "ignored": false
+                },
+                {
+                    "nodeId": "2",
+                    "parentId": "1",
+                    "role": {"type": "role", "value": "image"},
+                    "properties": [
+                        {"name": "alt", "value": {"type": "string", "value": "Dot"}},
+                        {"name": "url", "value": {"type": "string", "value": "https://example.com/dot.gif"}}
+                    ]
+                }
+            ]
+        }"#;
+
+        let tree: AxTree = serde_json::from_str(json).unwrap();
+        assert_eq!(tree.image_urls(), vec!["https://example.com/dot.gif".to_string()]);
+
+        let data_uri = "data:image/gif;base64,R0lGODlhAQABAAAAACw=";
+        let mut options = ConvertOptions::default();
+        options.inline_images.insert("https://example.com/dot.gif".to_string(), data_uri.to_string());
+        let md = axtree_to_markdown_with_options(&tree, &options);
+        assert_eq!(md, format!("![Dot]({})", data_uri));
+    }
+
+    #[test]
+    fn test_columns_reading_order() {
+        let json = r#"{
+            "nodes": [
+                {
+                    "nodeId": "1",
+                    "role": {"type": "role", "value": "RootWebArea"},
+                    "childIds": ["2", "3", "4", "5"],
+                    "ignored": false
+                },
+                {
+                    "nodeId": "2",
+                    "parentId": "1",
+                    "backendDOMNodeId": 12,
+                    "role": {"type": "role", "value": "paragraph"},
+                    "childIds": ["-2"]
+                },
+                {
+                    "nodeId": "-2",
+                    "parentId": "2",
+                    "role": {"type": "internalRole", "value": 158},
+                    "name": {"type": "computedString", "value": "Left one"}
+                },
+                {
+                    "nodeId": "3",
+                    "parentId": "1",
+                    "backendDOMNodeId": 13,
+                    "role": {"type": "role", "value": "paragraph"},
+                    "childIds": ["-3"]
+                },
+                {
+                    "nodeId": "-3",
+                    "parentId": "3",
+                    "role": {"type": "internalRole", "value": 158},
+                    "name": {"type": "computedString", "value": "Right one"}
+                },
+                {
+                    "nodeId": "4",
+                    "parentId": "1",
+                    "backendDOMNodeId": 14,
+                    "role": {"type": "role", "value": "paragraph"},
+                    "childIds": ["-4"]
+                },
+                {
+                    "nodeId": "-4",
+                    "parentId": "4",
+                    "role": {"type": "internalRole", "value": 158},
+                    "name": {"type": "computedString", "value": "Left two"}
+                },
+                {
+                    "nodeId": "5",
+                    "parentId": "1",
+                    "backendDOMNodeId": 15,
+                    "role": {"type": "role", "value": "paragraph"},
+                    "childIds": ["-5"]
+                },
+                {
+                    "nodeId": "-5",
+                    "parentId": "5",
+                    "role": {"type": "internalRole", "value": 158},
+                    "name": {"type": "computedString", "value": "Right two"}
+                }
+            ]
+        }"#;
+
+        let tree: AxTree = serde_json::from_str(json).unwrap();
+        let column = |x: f64, y: f64| BoundingBox { x, y, width: 400.0, height: 20.0 };
+        let mut options = ConvertOptions::default();
+        options.geometry.insert(12, column(0.0, 0.0));
+        options.geometry.insert(13, column(500.0, 0.0));
+        options.geometry.insert(14, column(0.0, 40.0));
+        options.geometry.insert(15, column(500.0, 40.0));
+
+        // Without the hint DOM order is preserved
+        let md = axtree_to_markdown_with_options(&tree, &options);
+        assert_eq!(md, "Left one\n\nRight one\n\nLeft two\n\nRight two");
+
+        options.columns = Some(2);
+        let md = axtree_to_markdown_with_options(&tree, &options);
+        assert_eq!(md, "Left one\n\nLeft two\n\nRight one\n\nRight two");
+    }
+
+    #[test]
+    fn test_no_footer_marker() {
+        let json = r#"{
+            "nodes": [
+                {
+                    "nodeId": "1",
+                    "role": {"type": "role", "value": "RootWebArea"},
+                    "childIds": ["2"],
+                    "ignored": false
+                },
+                {
+                    "nodeId": "2",
+                    "parentId": "1",
+                    "role": {"type": "role", "value": "contentinfo"},
+                    "childIds": ["3"]
+                },
+                {
+                    "nodeId": "3",
+                    "parentId": "2",
+                    "role": {"type": "role", "value": "paragraph"},
+                    "childIds": ["-1"]
+                },
+                {
+                    "nodeId": "-1",
+                    "parentId": "3",
+                    "role": {"type": "internalRole", "value": 158},
+                    "name": {"type": "computedString", "value": "Copyright 2024"}
+                }
+            ]
+        }"#;
+
+        let tree: AxTree = serde_json::from_str(json).unwrap();
+        let md = axtree_to_markdown(&tree);
+        assert!(md.contains("--- Footer ---"));
+
+        let options = ConvertOptions { no_footer_marker: true, ..Default::default() };
+        let md = axtree_to_markdown_with_options(&tree, &options);
+        assert!(!md.contains("Footer"));
+        assert!(md.contains("Copyright 2024"));
+    }
+
+    #[test]
+    fn test_internal_role_override() {
+        // A Chrome build that numbers StaticText as 160
+        let mut tree = AxTreeBuilder::new()
+            .node("2", "paragraph", "1")
+            .text("-1", "Renumbered text", "2")
+            .build();
+        tree.nodes[2].role.value = RoleValueContent::Internal(160);
+
+        assert_eq!(axtree_to_markdown(&tree), "");
+
+        let mut options = ConvertOptions::default();
+        options.internal_roles.insert(160, "StaticText");
+        let md = axtree_to_markdown_with_options(&tree, &options);
+        assert_eq!(md, "Renumbered text");
+        assert_eq!(options.internal_roles.name(STATIC_TEXT_ROLE), None);
+    }
+
+    #[test]
+    fn test_paragraph_conversion() {
+        let json = r#"{
+            "nodes": [
+                {
+                    "nodeId": "1",
+                    "role": {"type": "role", "value": "RootWebArea"},
+                    "childIds": ["2"],
+                    "ignored": false
+                },
+                {
+                    "nodeId": "2",
+                    "parentId": "1",
+                    "role": {"type": "role", "value": "paragraph"},
+                    "name": {"type": "computedString", "value": ""},
+                    "childIds": ["-1"]
                 },
                 {
                     "nodeId": "-1",
@@ -944,6 +3050,1226 @@ mod tests {
         assert!(md.contains("# Visible Heading"));
     }
 
+    #[test]
+    fn test_progressbar_value_range() {
+        let json = r#"{
+            "nodes": [
+                {
+                    "nodeId": "1",
+                    "role": {"type": "role", "value": "RootWebArea"},
+                    "childIds": ["2"],
+                    "ignored": false
+                },
+                {
+                    "nodeId": "2",
+                    "parentId": "1",
+                    "role": {"type": "role", "value": "progressbar"},
+                    "name": {"type": "computedString", "value": "Upload"},
+                    "properties": [
+                        {"name": "valuenow", "value": {"type": "integer", "value": 40}},
+                        {"name": "valuemin", "value": {"type": "integer", "value": 0}},
+                        {"name": "valuemax", "value": {"type": "integer", "value": 100}}
+                    ]
+                }
+            ]
+        }"#;
+
+        let tree: AxTree = serde_json::from_str(json).unwrap();
+        let md = axtree_to_markdown(&tree);
+        assert_eq!(md, "Upload: 40 (0–100)");
+
+        // Chrome reports the values of real pages as numbers
+        let json = json
+            .replace(r#""type": "integer", "value": 40"#, r#""type": "number", "value": 42.6"#)
+            .replace(r#""type": "integer""#, r#""type": "number""#);
+        let tree: AxTree = serde_json::from_str(&json).unwrap();
+        assert_eq!(axtree_to_markdown(&tree), "Upload: 43 (0–100)");
+    }
+
+    #[test]
+    fn test_serialize_round_trip() {
+        let json = std::fs::read_to_string("./src/test_axt_nodes.json").unwrap();
+        let tree: AxTree = serde_json::from_str(&json).unwrap();
+        let serialized = serde_json::to_string_pretty(&tree).unwrap();
+        let reparsed: AxTree = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(reparsed.nodes.len(), tree.nodes.len());
+        assert_eq!(serde_json::to_string_pretty(&reparsed).unwrap(), serialized);
+        assert_eq!(axtree_to_markdown(&reparsed), axtree_to_markdown(&tree));
+    }
+
+    #[test]
+    fn test_flatten() {
+        let json = std::fs::read_to_string("./src/test_axt_nodes.json").unwrap();
+        let tree: AxTree = serde_json::from_str(&json).unwrap();
+        let options = ConvertOptions { flatten: true, ..Default::default() };
+        let md = axtree_to_markdown_with_options(&tree, &options);
+        assert!(!md.contains('\n'));
+        assert!(!md.contains('#'));
+        assert!(!md.contains("- "));
+        assert!(!md.contains("]("));
+        assert!(md.contains("Tunnelcast"));
+        assert!(md.contains("I love deck building games"));
+    }
+
+    #[test]
+    fn test_footnotes() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "paragraph", "1")
+            .text("3", "Rust is memory safe", "2")
+            .node("4", "superscript", "2")
+            .node("5", "link", "4").str_prop("url", "https://example.com/post#fn1")
+            .text("6", "1", "5")
+            .node("7", "list", "1")
+            .node("8", "listItem", "7")
+            .node("9", "paragraph", "8")
+            .text("10", "See the Rust book. ", "9")
+            .node("11", "link", "9").str_prop("url", "https://example.com/post#fnref1")
+            .text("12", "↩", "11")
+            .build();
+
+        let options = ConvertOptions { footnotes: true, ..Default::default() };
+        let md = axtree_to_markdown_with_options(&tree, &options);
+        assert_eq!(md, "Rust is memory safe[^1]\n\n[^1]: See the Rust book.");
+    }
+
+    #[test]
+    fn test_citation_link() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "paragraph", "1")
+            .text("3", "Rust is memory safe.", "2")
+            .node("4", "superscript", "2")
+            .node("5", "link", "4").str_prop("url", "https://en.wikipedia.org/wiki/Rust#cite_note-1")
+            .text("6", "[1]", "5")
+            .text("7", " It is fast.", "2")
+            .node("8", "list", "1")
+            .node("9", "listItem", "8")
+            .text("10", "The Rust Reference. ", "9")
+            .node("11", "link", "9").str_prop("url", "https://en.wikipedia.org/wiki/Rust#cite_ref-1")
+            .text("12", "↩", "11")
+            .build();
+
+        let md = axtree_to_markdown(&tree);
+        assert!(md.starts_with("Rust is memory safe.[1] It is fast.\n\n- "));
+        assert!(!md.contains("[[1]]"));
+
+        let options = ConvertOptions { footnotes: true, ..Default::default() };
+        let md = axtree_to_markdown_with_options(&tree, &options);
+        assert_eq!(md, "Rust is memory safe.[^1] It is fast.\n\n[^1]: The Rust Reference.");
+    }
+
+    #[test]
+    fn test_missing_children() {
+        let mut tree = AxTreeBuilder::new()
+            .node("2", "paragraph", "1")
+            .text("-1", "Still rendered", "2")
+            .build();
+        tree.nodes[1].child_ids.as_mut().unwrap().push("99".to_string());
+
+        let missing = tree.missing_children();
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].to_string(), "node 2 references missing child 99");
+
+        // Conversion stays lenient
+        assert_eq!(axtree_to_markdown(&tree), "Still rendered");
+    }
+
+    #[test]
+    fn test_strip_nav_links() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "banner", "1")
+            .node("3", "navigation", "2")
+            .node("4", "link", "3").str_prop("url", "https://example.com/")
+            .text("5", "Home", "4")
+            .node("6", "article", "1")
+            .node("7", "link", "6").str_prop("url", "https://example.com/source")
+            .text("8", "Source", "7")
+            .build();
+
+        let md = axtree_to_markdown(&tree);
+        assert!(md.contains("[Home](https://example.com/)"));
+
+        let options = ConvertOptions { strip_nav_links: true, ..Default::default() };
+        let md = axtree_to_markdown_with_options(&tree, &options);
+        assert_eq!(md, "Home\n[Source](https://example.com/source)");
+    }
+
+    #[test]
+    fn test_landmark_stack() {
+        let options = ConvertOptions::default();
+        let mut ctx = ConvertContext::new(&options);
+        assert_eq!(ctx.current_landmark(), None);
+
+        assert!(ctx.enter_landmark(Some("main")));
+        assert!(!ctx.enter_landmark(Some("generic")));
+        assert!(ctx.enter_landmark(Some("navigation")));
+        assert_eq!(ctx.current_landmark(), Some("navigation"));
+        assert!(ctx.in_landmark("main"));
+
+        ctx.landmarks.pop();
+        assert_eq!(ctx.current_landmark(), Some("main"));
+        assert!(!ctx.in_landmark("navigation"));
+    }
+
+    #[test]
+    fn test_ast_blocks() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "heading", "1").int_prop("level", 2)
+            .text("3", "Shopping", "2")
+            .node("4", "list", "1")
+            .node("5", "listItem", "4")
+            .text("6", "Apples", "5")
+            .node("7", "listItem", "4")
+            .text("8", "Pears", "7")
+            .build();
+
+        let blocks = axtree_to_ast(&tree, &ConvertOptions::default());
+        let json = serde_json::to_value(&blocks).unwrap();
+        assert_eq!(json, serde_json::json!([
+            {"type": "heading", "level": 2, "text": "Shopping"},
+            {"type": "list", "ordered": false, "items": ["Apples", "Pears"]}
+        ]));
+    }
+
+    #[test]
+    fn test_ast_follows_conversion() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "navigation", "1")
+            .node("3", "link", "2").str_prop("url", "/home")
+            .text("4", "Home", "3")
+            .node("5", "blockquote", "1")
+            .node("6", "paragraph", "5")
+            .text("7", "Quoted | text", "6")
+            .node("8", "table", "1")
+            .node("9", "row", "8")
+            .node("10", "columnheader", "9")
+            .text("11", "Name", "10")
+            .node("12", "row", "8")
+            .node("13", "cell", "12")
+            .text("14", "a | b", "13")
+            .node("15", "code", "1")
+            .text("16", "cargo run", "15")
+            .build();
+
+        let options = ConvertOptions { strip_nav_links: true, ..Default::default() };
+        let json = serde_json::to_value(axtree_to_ast(&tree, &options)).unwrap();
+        assert_eq!(json, serde_json::json!([
+            {"type": "paragraph", "text": "Home"},
+            {"type": "quote", "blocks": [{"type": "paragraph", "text": "Quoted | text"}]},
+            {"type": "table", "rows": [["Name"], ["a | b"]]},
+            {"type": "code", "text": "cargo run"}
+        ]));
+        let md = axtree_to_markdown_with_options(&tree, &options);
+        assert!(md.contains("| a \\| b |"));
+    }
+
+    #[test]
+    fn test_unexpected_role_value() {
+        let json = r#"{
+            "nodes": [
+                {
+                    "nodeId": "1",
+                    "role": {"type": "role", "value": "RootWebArea"},
+                    "childIds": ["2"],
+                    "ignored": false
+                },
+                {
+                    "nodeId": "2",
+                    "parentId": "1",
+                    "role": {"type": "role", "value": null},
+                    "childIds": ["3"]
+                },
+                {
+                    "nodeId": "3",
+                    "parentId": "2",
+                    "role": {"type": "role", "value": "paragraph"},
+                    "childIds": ["-1"]
+                },
+                {
+                    "nodeId": "-1",
+                    "parentId": "3",
+                    "role": {"type": "internalRole", "value": 158},
+                    "name": {"type": "computedString", "value": "Inside odd role"}
+                }
+            ]
+        }"#;
+
+        let tree: AxTree = serde_json::from_str(json).unwrap();
+        assert!(matches!(tree.nodes[1].role.value, RoleValueContent::Other(serde_json::Value::Null)));
+        assert_eq!(axtree_to_markdown(&tree), "Inside odd role");
+    }
+
+    #[test]
+    fn test_hr_style() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "paragraph", "1")
+            .text("3", "Above", "2")
+            .node("4", "separator", "1")
+            .node("5", "paragraph", "1")
+            .text("6", "Below", "5")
+            .build();
+
+        assert_eq!(axtree_to_markdown(&tree), "Above\n\n---\nBelow");
+
+        let options = ConvertOptions { hr_style: HrStyle::Asterisks, ..Default::default() };
+        let md = axtree_to_markdown_with_options(&tree, &options);
+        assert_eq!(md, "Above\n\n***\nBelow");
+    }
+
+    #[test]
+    fn test_debug_roles() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "heading", "1").int_prop("level", 1)
+            .text("3", "Title", "2")
+            .node("4", "paragraph", "1")
+            .text("5", "Body", "4")
+            .build();
+
+        let options = ConvertOptions { debug_roles: true, ..Default::default() };
+        let md = axtree_to_markdown_with_options(&tree, &options);
+        assert_eq!(md, "# Title\n<!-- heading -->\n\nBody\n<!-- paragraph -->");
+    }
+
+    #[test]
+    fn test_streaming_writer_matches_in_memory() {
+        let json = std::fs::read_to_string("./src/test_axt_nodes.json").unwrap();
+        let tree: AxTree = serde_json::from_str(&json).unwrap();
+        let options = ConvertOptions::default();
+
+        let mut sink: Vec<u8> = Vec::new();
+        axtree_to_markdown_writer(&tree, &options, &mut sink).unwrap();
+        let streamed = String::from_utf8(sink).unwrap();
+        assert_eq!(streamed, axtree_to_markdown_with_options(&tree, &options));
+    }
+
+    #[test]
+    fn test_table_with_column_headers() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "table", "1")
+            .node("3", "row", "2")
+            .node("4", "columnheader", "3").text("5", "Name", "4")
+            .node("6", "columnheader", "3").text("7", "Age", "6")
+            .node("8", "row", "2")
+            .node("9", "cell", "8").text("10", "Ada", "9")
+            .node("11", "cell", "8").text("12", "36", "11")
+            .build();
+
+        let md = axtree_to_markdown(&tree);
+        assert_eq!(md, "| Name | Age |\n| --- | --- |\n| Ada | 36 |");
+    }
+
+    #[test]
+    fn test_table_without_headers_pads_ragged_rows() {
+        let json = r#"{
+            "nodes": [
+                {"nodeId": "1", "role": {"type": "role", "value": "RootWebArea"}, "childIds": ["2"]},
+                {"nodeId": "2", "parentId": "1", "role": {"type": "role", "value": "table"}, "childIds": ["3", "6"]},
+                {"nodeId": "3", "parentId": "2", "role": {"type": "role", "value": "row"}, "childIds": ["4", "5"]},
+                {"nodeId": "4", "parentId": "3", "role": {"type": "role", "value": "cell"}, "childIds": ["8"]},
+                {"nodeId": "5", "parentId": "3", "role": {"type": "role", "value": "cell"}, "childIds": ["9"]},
+                {"nodeId": "6", "parentId": "2", "role": {"type": "role", "value": "row"}, "childIds": ["7"]},
+                {"nodeId": "7", "parentId": "6", "role": {"type": "role", "value": "cell"}, "childIds": ["10"]},
+                {"nodeId": "8", "parentId": "4", "role": {"type": "internalRole", "value": 158},
+                 "name": {"type": "computedString", "value": "a1"}},
+                {"nodeId": "9", "parentId": "5", "role": {"type": "internalRole", "value": 158},
+                 "name": {"type": "computedString", "value": "b1"}},
+                {"nodeId": "10", "parentId": "7", "role": {"type": "internalRole", "value": 158},
+                 "name": {"type": "computedString", "value": "a2"}}
+            ]
+        }"#;
+
+        let tree: AxTree = serde_json::from_str(json).unwrap();
+        let md = axtree_to_markdown(&tree);
+        assert_eq!(md, "| a1 | b1 |\n| --- | --- |\n| a2 |  |");
+    }
+
+    #[test]
+    fn test_table_with_row_headers() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "table", "1")
+            .node("3", "rowgroup", "2")
+            .node("4", "row", "3")
+            .node("5", "rowheader", "4").text("6", "Revenue", "5")
+            .node("7", "cell", "4").text("8", "$10", "7")
+            .node("9", "cell", "4").text("10", "$12", "9")
+            .node("11", "row", "3")
+            .node("12", "cell", "11").text("13", "$4", "12")
+            .node("14", "rowheader", "11").text("15", "Costs", "14")
+            .build();
+
+        let md = axtree_to_markdown(&tree);
+        assert_eq!(md, "|  |  |  |\n| --- | --- | --- |\n| Revenue | $10 | $12 |\n| Costs | $4 |  |");
+    }
+
+    #[test]
+    fn test_sparse_grid_uses_indices() {
+        // A virtualized grid showing rows 1, 40 and 41 of a larger sheet
+        // with DOM order shuffled and an empty middle column omitted
+        let tree = AxTreeBuilder::new()
+            .node("2", "grid", "1")
+            .node("3", "rowgroup", "2")
+            .node("4", "row", "3").int_prop("rowindex", 41)
+            .node("5", "gridcell", "4").int_prop("colindex", 3).text("6", "done", "5")
+            .node("7", "gridcell", "4").int_prop("colindex", 1).text("8", "Write docs", "7")
+            .node("9", "row", "3").int_prop("rowindex", 1)
+            .node("10", "columnheader", "9").int_prop("colindex", 1).text("11", "Task", "10")
+            .node("12", "columnheader", "9").int_prop("colindex", 2).text("13", "Owner", "12")
+            .node("14", "columnheader", "9").int_prop("colindex", 3).text("15", "Status", "14")
+            .node("16", "row", "3").int_prop("rowindex", 40)
+            .node("17", "gridcell", "16").int_prop("colindex", 1).text("18", "Fix bug", "17")
+            .node("19", "gridcell", "16").int_prop("colindex", 2).text("20", "Ada", "19")
+            .node("21", "gridcell", "16").int_prop("colindex", 3).text("22", "open", "21")
+            .build();
+
+        let md = axtree_to_markdown(&tree);
+        assert_eq!(
+            md,
+            "| Task | Owner | Status |\n| --- | --- | --- |\n| Fix bug | Ada | open |\n| Write docs |  | done |"
+        );
+    }
+
+    #[test]
+    fn test_list_marker_not_doubled() {
+        let json = r#"{
+            "nodes": [
+                {"nodeId": "1", "ignored": false, "role": {"type": "role", "value": "RootWebArea"}, "childIds": ["2"]},
+                {"nodeId": "2", "parentId": "1", "ignored": false, "role": {"type": "role", "value": "list"}, "childIds": ["3", "7"]},
+                {"nodeId": "3", "parentId": "2", "ignored": false, "role": {"type": "role", "value": "listItem"}, "childIds": ["4", "6"]},
+                {"nodeId": "4", "parentId": "3", "ignored": false, "role": {"type": "internalRole", "value": "ListMarker"}, "childIds": ["5"]},
+                {"nodeId": "5", "parentId": "4", "ignored": false, "role": {"type": "internalRole", "value": 158}, "name": {"type": "computedString", "value": "• "}, "childIds": []},
+                {"nodeId": "6", "parentId": "3", "ignored": false, "role": {"type": "internalRole", "value": 158}, "name": {"type": "computedString", "value": "Apples"}, "childIds": []},
+                {"nodeId": "7", "parentId": "2", "ignored": false, "role": {"type": "role", "value": "listItem"}, "childIds": ["8", "10"]},
+                {"nodeId": "8", "parentId": "7", "ignored": false, "role": {"type": "internalRole", "value": "ListMarker"}, "childIds": ["9"]},
+                {"nodeId": "9", "parentId": "8", "ignored": false, "role": {"type": "internalRole", "value": 158}, "name": {"type": "computedString", "value": "2. "}, "childIds": []},
+                {"nodeId": "10", "parentId": "7", "ignored": false, "role": {"type": "internalRole", "value": 158}, "name": {"type": "computedString", "value": "Pears"}, "childIds": []}
+            ]
+        }"#;
+
+        let tree: AxTree = serde_json::from_str(json).unwrap();
+        let md = axtree_to_markdown(&tree);
+        assert_eq!(md, "- Apples\n- Pears");
+
+        let options = ConvertOptions { flatten: true, ..Default::default() };
+        assert_eq!(axtree_to_markdown_with_options(&tree, &options), "Apples Pears");
+    }
+
+    #[test]
+    fn test_toc_depth() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "heading", "1").int_prop("level", 1)
+            .text("3", "Guide", "2")
+            .node("4", "heading", "1").int_prop("level", 2)
+            .text("5", "Install", "4")
+            .node("6", "heading", "1").int_prop("level", 3)
+            .text("7", "From source", "6")
+            .node("8", "heading", "1").int_prop("level", 2)
+            .text("9", "Usage", "8")
+            .build();
+
+        let options = ConvertOptions { toc: true, toc_depth: Some(2), ..Default::default() };
+        let md = axtree_to_markdown_with_options(&tree, &options);
+        assert_eq!(
+            md,
+            "- [Guide](#guide)\n  - [Install](#install)\n  - [Usage](#usage)\n\n\
+             # Guide\n\n## Install\n\n### From source\n\n## Usage"
+        );
+
+        let options = ConvertOptions { toc: true, ..Default::default() };
+        let md = axtree_to_markdown_with_options(&tree, &options);
+        assert!(md.starts_with("- [Guide](#guide)\n  - [Install](#install)\n    - [From source](#from-source)\n"));
+    }
+
+    #[test]
+    fn test_toc_slug_style() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "heading", "1").int_prop("level", 2)
+            .text("3", "Setup - macOS", "2")
+            .build();
+
+        let options = ConvertOptions { toc: true, ..Default::default() };
+        let md = axtree_to_markdown_with_options(&tree, &options);
+        assert!(md.starts_with("- [Setup - macOS](#setup---macos)\n"));
+
+        let options = ConvertOptions { toc: true, slug_style: SlugStyle::Gitlab, ..Default::default() };
+        let md = axtree_to_markdown_with_options(&tree, &options);
+        assert!(md.starts_with("- [Setup - macOS](#setup-macos)\n"));
+    }
+
+    #[test]
+    fn test_escape_markdown() {
+        let axtree = AxTreeBuilder::new()
+            .node("2", "paragraph", "1")
+            .text("3", "Learn C# with our *stars* plan ", "2")
+            .node("4", "code", "2")
+            .text("5", "a_b * c", "4")
+            .build();
+
+        let options = ConvertOptions { escape_markdown: true, ..Default::default() };
+        let markdown = axtree_to_markdown_with_options(&axtree, &options);
+        assert_eq!(markdown, "Learn C\\# with our \\*stars\\* plan `a_b * c`");
+
+        let markdown = axtree_to_markdown(&axtree);
+        assert_eq!(markdown, "Learn C# with our *stars* plan `a_b * c`");
+    }
+
+    #[test]
+    fn test_link_named_by_title_attribute() {
+        let json = r#"{
+            "nodes": [
+                {
+                    "nodeId": "1",
+                    "ignored": false,
+                    "role": {"type": "role", "value": "RootWebArea"},
+                    "childIds": ["2"]
+                },
+                {
+                    "nodeId": "2",
+                    "parentId": "1",
+                    "ignored": false,
+                    "role": {"type": "role", "value": "link"},
+                    "name": {
+                        "type": "computedString",
+                        "value": "",
+                        "sources": [
+                            {"type": "relatedElement", "attribute": "aria-labelledby"},
+                            {"type": "attribute", "attribute": "aria-label"},
+                            {"type": "contents"},
+                            {
+                                "type": "attribute",
+                                "attribute": "title",
+                                "attributeValue": {"type": "string", "value": "Settings"},
+                                "value": {"type": "computedString", "value": "Settings"}
+                            }
+                        ]
+                    },
+                    "properties": [
+                        {"name": "url", "value": {"type": "string", "value": "https://example.com/settings"}}
+                    ],
+                    "childIds": []
+                }
+            ]
+        }"#;
+
+        let axtree: AxTree = serde_json::from_str(json).unwrap();
+        let markdown = axtree_to_markdown(&axtree);
+        assert_eq!(markdown, "[Settings](https://example.com/settings)");
+    }
+
+    #[test]
+    fn test_raw_entities() {
+        let axtree = AxTreeBuilder::new()
+            .node("2", "paragraph", "1")
+            .text("3", "Fish & chips <3", "2")
+            .build();
+
+        let options = ConvertOptions { raw_entities: true, ..Default::default() };
+        let markdown = axtree_to_markdown_with_options(&axtree, &options);
+        assert_eq!(markdown, "Fish &amp; chips &lt;3");
+    }
+
+    #[test]
+    fn test_aria_hidden_subtree_excluded() {
+        let axtree = AxTreeBuilder::new()
+            .node("2", "navigation", "1").ignored("ariaHiddenElement")
+            .node("3", "link", "2").str_prop("url", "/menu").ignored("ariaHiddenSubtree")
+            .text("4", "Off-screen menu", "3")
+            .node("5", "paragraph", "1")
+            .text("6", "Visible text", "5")
+            .build();
+
+        let markdown = axtree_to_markdown(&axtree);
+        assert_eq!(markdown, "Visible text");
+
+        let options = ConvertOptions { include_aria_hidden: true, ..Default::default() };
+        let markdown = axtree_to_markdown_with_options(&axtree, &options);
+        assert!(markdown.contains("Off-screen menu"));
+    }
+
+    #[test]
+    fn test_hidden_and_presentation_nodes() {
+        let axtree = AxTreeBuilder::new()
+            .node("2", "image", "1").name("Decorative swirl").str_prop("url", "/swirl.png").bool_prop("hidden", true)
+            .node("3", "heading", "2").int_prop("level", 2)
+            .text("4", "Visible title", "3")
+            .node("5", "presentation", "1").name("Layout cell")
+            .node("6", "paragraph", "5")
+            .text("7", "Body text", "6")
+            .build();
+
+        let markdown = axtree_to_markdown(&axtree);
+        assert_eq!(markdown, "## Visible title\n\nBody text");
+    }
+
+    #[test]
+    fn test_link_run_style() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "generic", "1")
+            .node("3", "link", "2").str_prop("url", "/rust")
+            .text("4", "rust", "3")
+            .node("5", "link", "2").str_prop("url", "/go")
+            .text("6", "go", "5")
+            .node("7", "link", "2").str_prop("url", "/zig")
+            .text("8", "zig", "7")
+            .build();
+
+        let md = axtree_to_markdown(&tree);
+        assert_eq!(md, "[rust](/rust) [go](/go) [zig](/zig)");
+
+        let options = ConvertOptions { link_run_style: LinkRunStyle::Bullets, ..Default::default() };
+        let md = axtree_to_markdown_with_options(&tree, &options);
+        assert_eq!(md, "- [rust](/rust)\n- [go](/go)\n- [zig](/zig)");
+    }
+
+    #[test]
+    fn test_callouts() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "paragraph", "1")
+            .text("3", "Install the CLI first.", "2")
+            .node("4", "note", "1")
+            .node("5", "paragraph", "4")
+            .text("6", "Requires Chrome 120 or later.", "5")
+            .node("7", "paragraph", "4")
+            .text("8", "Older versions may work.", "7")
+            .node("9", "complementary", "1").name("Warning")
+            .node("10", "paragraph", "9")
+            .text("11", "This deletes your data.", "10")
+            .node("12", "complementary", "1")
+            .node("13", "paragraph", "12")
+            .text("14", "Related reading", "13")
+            .build();
+
+        let options = ConvertOptions { callouts: true, ..Default::default() };
+        let md = axtree_to_markdown_with_options(&tree, &options);
+        assert_eq!(
+            md,
+            "Install the CLI first.\n\n\
+             > [!NOTE]\n> Requires Chrome 120 or later.\n>\n> Older versions may work.\n\n\
+             > [!WARNING]\n> This deletes your data.\n\n\
+             > Related reading"
+        );
+
+        let md = axtree_to_markdown(&tree);
+        assert!(!md.contains('>'));
+    }
+
+    #[test]
+    fn test_added_since_keeps_new_subtrees() {
+        let before = AxTreeBuilder::new()
+            .node("2", "feed", "1")
+            .node("3", "article", "2")
+            .node("4", "paragraph", "3")
+            .text("5", "First post", "4")
+            .build();
+        let after = AxTreeBuilder::new()
+            .node("2", "feed", "1")
+            .node("3", "article", "2")
+            .node("4", "paragraph", "3")
+            .text("5", "First post", "4")
+            .node("6", "article", "2")
+            .node("7", "paragraph", "6")
+            .text("8", "Second post", "7")
+            .build();
+
+        let added = after.added_since(&before);
+        assert_eq!(axtree_to_markdown(&added), "Second post");
+        assert!(axtree_to_markdown(&after.added_since(&after)).is_empty());
+    }
+
+    #[test]
+    fn test_without_dom_nodes() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "article", "1").backend_id(10)
+            .node("3", "paragraph", "2").backend_id(11)
+            .text("4", "The story", "3")
+            .node("5", "complementary", "1").backend_id(20)
+            .node("6", "heading", "5").backend_id(21)
+            .text("7", "Related articles", "6")
+            .node("8", "link", "5").backend_id(22).str_prop("url", "https://example.com/other")
+            .text("9", "Another story", "8")
+            .build();
+
+        let pruned = tree.without_dom_nodes(&HashSet::from([20]));
+        assert_eq!(axtree_to_markdown(&pruned), "The story");
+        assert!(pruned.find_node("9").is_none());
+        assert_eq!(pruned.find_root().unwrap().child_ids, Some(vec!["2".to_string()]));
+    }
+
+    #[test]
+    fn test_empty_tree() {
+        let tree = AxTree { nodes: Vec::new() };
+        assert_eq!(tree.emptiness(), Some(EmptyTree::NoNodes));
+        assert_eq!(tree.emptiness().unwrap().to_string(), "accessibility tree has no nodes");
+        assert_eq!(axtree_to_markdown(&tree), "");
+
+        let tree = AxTreeBuilder::new()
+            .node("2", "generic", "1").ignored("uninteresting")
+            .build();
+        assert_eq!(tree.emptiness(), Some(EmptyTree::OnlyIgnored));
+
+        let tree = AxTreeBuilder::new()
+            .node("2", "paragraph", "1")
+            .text("3", "Hello", "2")
+            .build();
+        assert_eq!(tree.emptiness(), None);
+    }
+
+    #[test]
+    fn test_tree_without_root_web_area() {
+        let json = r#"{
+            "nodes": [
+                {"nodeId": "1", "ignored": false, "role": {"type": "role", "value": "paragraph"}, "childIds": ["2"]},
+                {"nodeId": "2", "parentId": "1", "ignored": false, "role": {"type": "internalRole", "value": 158}, "name": {"type": "computedString", "value": "Inside the frame"}, "childIds": []},
+                {"nodeId": "3", "ignored": false, "role": {"type": "role", "value": "heading"}, "childIds": ["4"], "properties": [{"name": "level", "value": {"type": "integer", "value": 2}}]},
+                {"nodeId": "4", "parentId": "3", "ignored": false, "role": {"type": "internalRole", "value": 158}, "name": {"type": "computedString", "value": "A second root"}, "childIds": []}
+            ]
+        }"#;
+
+        let tree: AxTree = serde_json::from_str(json).unwrap();
+        assert!(tree.find_root().is_none());
+        assert_eq!(tree.roots().len(), 2);
+        assert_eq!(axtree_to_markdown(&tree), "Inside the frame\n\n## A second root");
+    }
+
+    #[test]
+    fn test_prettify_whitespace() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "code", "1")
+            .text("3", "if ready { start(); }", "2")
+            .node("4", "InlineTextBox", "3").name("if ready {")
+            .node("5", "InlineTextBox", "3").name("    start();")
+            .node("6", "InlineTextBox", "3").name("}")
+            .build();
+
+        let options = ConvertOptions { prettify_whitespace: true, ..Default::default() };
+        let md = axtree_to_markdown_with_options(&tree, &options);
+        assert_eq!(md, "```\nif ready {\n    start();\n}\n```");
+    }
+
+    #[test]
+    fn test_max_bytes() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "heading", "1").int_prop("level", 1)
+            .text("3", "Title", "2")
+            .node("4", "paragraph", "1")
+            .text("5", "First paragraph.", "4")
+            .node("6", "list", "1")
+            .node("7", "listItem", "6")
+            .text("8", "One", "7")
+            .node("9", "listItem", "6")
+            .text("10", "Two", "9")
+            .node("11", "paragraph", "1")
+            .text("12", "A closing paragraph that doesn't fit.", "11")
+            .build();
+
+        let full = axtree_to_markdown(&tree);
+        for max_bytes in [0, 10, 30, 40, full.len()] {
+            let options = ConvertOptions { max_bytes: Some(max_bytes), ..Default::default() };
+            let md = axtree_to_markdown_with_options(&tree, &options);
+            assert!(md.len() <= max_bytes);
+            assert!(full.starts_with(&md));
+        }
+
+        let options = ConvertOptions { max_bytes: Some(40), ..Default::default() };
+        let md = axtree_to_markdown_with_options(&tree, &options);
+        assert_eq!(md, "# Title\n\nFirst paragraph.\n\n- One\n- Two");
+    }
+
+    #[test]
+    fn test_max_bytes_keeps_fences_and_tables_whole() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "paragraph", "1")
+            .text("3", "Intro.", "2")
+            .node("4", "code", "1")
+            .text("5", "fn main() {\n    run();\n}", "4")
+            .node("6", "table", "1")
+            .node("7", "row", "6")
+            .node("8", "columnheader", "7")
+            .text("9", "Name", "8")
+            .node("10", "row", "6")
+            .node("11", "cell", "10")
+            .text("12", "headful", "11")
+            .build();
+
+        let full = axtree_to_markdown(&tree);
+        let fence_ends = full.find("}\n```").unwrap() + "}\n```".len();
+        for max_bytes in 0..=full.len() {
+            let options = ConvertOptions { max_bytes: Some(max_bytes), ..Default::default() };
+            let md = axtree_to_markdown_with_options(&tree, &options);
+            assert!(full.starts_with(&md));
+            // Cut before the fence closes or inside the table, nothing of
+            // that block is kept
+            assert!(md.len() <= "Intro.".len() || md.len() >= fence_ends, "{:?}", md);
+            assert!(md.len() <= fence_ends || md == full, "{:?}", md);
+        }
+    }
+
+    #[test]
+    fn test_heading_name_not_repeated() {
+        // The heading's name is computed from the same text its children
+        // hold, and the empty anchor means it isn't only static text
+        let tree = AxTreeBuilder::new()
+            .node("2", "heading", "1").int_prop("level", 1).name("Hello World")
+            .text("3", "Hello World", "2")
+            .node("4", "generic", "2")
+            .node("5", "heading", "1").int_prop("level", 2).name("Hello World")
+            .node("6", "emphasis", "5")
+            .text("7", "Hello", "6")
+            .text("8", " World", "5")
+            .build();
+
+        let md = axtree_to_markdown(&tree);
+        assert_eq!(md, "# Hello World\n\n## *Hello* World");
+    }
+
+    #[test]
+    fn test_dedupe_adjacent_links() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "link", "1").str_prop("url", "https://example.com/post")
+            .node("3", "image", "2").str_prop("url", "https://example.com/thumb.png")
+            .node("4", "link", "1").str_prop("url", "https://example.com/post")
+            .text("5", "Read the post", "4")
+            .node("6", "link", "1").str_prop("url", "https://example.com/about")
+            .text("7", "About", "6")
+            .build();
+
+        let md = axtree_to_markdown(&tree);
+        assert!(md.contains("[Read the post](https://example.com/post)"));
+        assert!(md.contains("thumb.png"));
+
+        let options = ConvertOptions { dedupe_adjacent_links: true, ..Default::default() };
+        let md = axtree_to_markdown_with_options(&tree, &options);
+        assert_eq!(md, "[Read the post](https://example.com/post) [About](https://example.com/about)");
+    }
+
+    #[test]
+    fn test_merge_subtitles() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "heading", "1").int_prop("level", 1)
+            .text("3", "Rust in Production", "2")
+            .node("4", "heading", "1").int_prop("level", 2)
+            .text("5", "Lessons from five years of services", "4")
+            .node("6", "paragraph", "1")
+            .text("7", "It started with a rewrite.", "6")
+            .node("8", "heading", "1").int_prop("level", 2)
+            .text("9", "Background", "8")
+            .node("10", "heading", "1").int_prop("level", 2)
+            .text("11", "Same level", "10")
+            .build();
+
+        let md = axtree_to_markdown(&tree);
+        assert!(md.starts_with("# Rust in Production\n\n## Lessons from five years of services\n\n"));
+
+        // Only a lower-level heading right after another is a subtitle
+        let options = ConvertOptions { merge_subtitles: true, ..Default::default() };
+        let md = axtree_to_markdown_with_options(&tree, &options);
+        assert_eq!(
+            md,
+            "# Rust in Production\n*Lessons from five years of services*\n\nIt started with a rewrite.\n\n## Background\n\n## Same level"
+        );
+
+        // Merged subtitles aren't listed in the table of contents
+        let options = ConvertOptions { merge_subtitles: true, toc: true, ..Default::default() };
+        let md = axtree_to_markdown_with_options(&tree, &options);
+        assert!(md.starts_with(
+            "- [Rust in Production](#rust-in-production)\n  - [Background](#background)\n  - [Same level](#same-level)\n\n# Rust"
+        ));
+    }
+
+    #[test]
+    fn test_max_depth() {
+        // A chain of wrappers with a paragraph at the bottom
+        let chain = |depth: usize| {
+            let mut builder = AxTreeBuilder::new().node("2", "paragraph", "1").text("3", "Shallow", "2");
+            for i in 0..depth {
+                let parent = if i == 0 { "1".to_string() } else { (i + 3).to_string() };
+                builder = builder.node(&(i + 4).to_string(), "generic", &parent);
+            }
+            let parent = if depth == 0 { "1".to_string() } else { (depth + 3).to_string() };
+            builder
+                .node(&(depth + 4).to_string(), "paragraph", &parent)
+                .text(&(depth + 5).to_string(), "Deep", &(depth + 4).to_string())
+                .build()
+        };
+
+        assert_eq!(axtree_to_markdown(&chain(10)), "Shallow\n\nDeep");
+        let options = ConvertOptions { max_depth: Some(5), ..Default::default() };
+        let md = axtree_to_markdown_with_options(&chain(10), &options);
+        assert_eq!(md, "Shallow\n\n<!-- truncated: nested deeper than 5 levels -->");
+
+        // Deep enough to overflow the stack without a limit
+        let md = axtree_to_markdown(&chain(5_000));
+        assert_eq!(md, "Shallow\n\n<!-- truncated: nested deeper than 256 levels -->");
+
+        // A chain of inline wrappers below a block, walked for its text
+        let inline_chain = |role: &str, depth: usize| {
+            let mut builder = AxTreeBuilder::new()
+                .node("2", "paragraph", "1")
+                .text("3", "Shallow", "2")
+                .node("4", role, "1");
+            if role == "heading" {
+                builder = builder.int_prop("level", 2);
+            }
+            for i in 0..depth {
+                builder = builder.node(&(i + 5).to_string(), "generic", &(i + 4).to_string());
+            }
+            builder.text(&(depth + 5).to_string(), "Deep", &(depth + 4).to_string()).build()
+        };
+
+        assert_eq!(axtree_to_markdown(&inline_chain("paragraph", 10)), "Shallow\n\nDeep");
+        assert_eq!(axtree_to_markdown(&inline_chain("heading", 10)), "Shallow\n\n## Deep");
+        for role in ["paragraph", "heading"] {
+            let md = axtree_to_markdown(&inline_chain(role, 5_000));
+            assert_eq!(md, "Shallow\n\n<!-- truncated: nested deeper than 256 levels -->", "{role}");
+        }
+    }
+
+    #[test]
+    fn test_min_text_length() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "paragraph", "1")
+            .text("3", "Shipping is free on orders over $50.", "2")
+            .node("4", "paragraph", "1")
+            .text("5", "×", "4")
+            .node("6", "paragraph", "1")
+            .text("7", "OK", "6")
+            .node("8", "link", "1").str_prop("url", "https://example.com/")
+            .text("9", "Go", "8")
+            .build();
+
+        let options = ConvertOptions { min_text_length: 5, ..Default::default() };
+        let md = axtree_to_markdown_with_options(&tree, &options);
+        assert_eq!(md, "Shipping is free on orders over $50.\n\n[Go](https://example.com/)");
+
+        let md = axtree_to_markdown(&tree);
+        assert!(md.contains("OK"));
+    }
+
+    #[test]
+    fn test_table_header_rowgroup() {
+        // The body row group comes before the header row group in the tree
+        let tree = AxTreeBuilder::new()
+            .node("2", "table", "1")
+            .node("3", "rowgroup", "2")
+            .node("4", "row", "3")
+            .node("5", "cell", "4")
+            .text("6", "Alice", "5")
+            .node("7", "cell", "4")
+            .text("8", "30", "7")
+            .node("9", "rowgroup", "2")
+            .node("10", "row", "9")
+            .node("11", "columnheader", "10")
+            .text("12", "Name", "11")
+            .node("13", "columnheader", "10")
+            .text("14", "Age", "13")
+            .node("15", "row", "9")
+            .node("16", "columnheader", "15")
+            .text("17", "(given)", "16")
+            .node("18", "cell", "15")
+            .text("19", "(years)", "18")
+            .build();
+
+        let md = axtree_to_markdown(&tree);
+        assert_eq!(
+            md,
+            "| Name | Age |\n| --- | --- |\n| (given) | (years) |\n| Alice | 30 |"
+        );
+    }
+
+    #[test]
+    fn test_merge_trees() {
+        let first = AxTreeBuilder::new()
+            .node("2", "paragraph", "1")
+            .text("3", "Page one", "2")
+            .build();
+        let second = AxTreeBuilder::new()
+            .node("2", "paragraph", "1")
+            .text("3", "Page two", "2")
+            .build();
+
+        let merged = AxTree::merge(vec![first.clone(), second.clone()], Some("separator"));
+        assert!(merged.missing_children().is_empty());
+        assert_eq!(axtree_to_markdown(&merged), "Page one\n\n---\nPage two");
+
+        let merged = AxTree::merge(vec![first, second], None);
+        assert_eq!(axtree_to_markdown(&merged), "Page one\n\nPage two");
+    }
+
+    #[test]
+    fn test_blockquote() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "paragraph", "1")
+            .text("3", "As the saying goes:", "2")
+            .node("4", "blockquote", "1")
+            .node("5", "paragraph", "4")
+            .text("6", "Simple is better than complex.", "5")
+            .node("7", "blockquote", "4")
+            .node("8", "paragraph", "7")
+            .text("9", "Flat is better than nested.", "8")
+            .build();
+
+        let md = axtree_to_markdown(&tree);
+        assert_eq!(
+            md,
+            "As the saying goes:\n\n> Simple is better than complex.\n>\n> > Flat is better than nested."
+        );
+    }
+
+    #[test]
+    fn test_inline_code() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "paragraph", "1")
+            .text("3", "Run ", "2")
+            .node("4", "code", "2")
+            .text("5", "cargo build", "4")
+            .text("6", " first.", "2")
+            .node("7", "code", "1")
+            .text("8", "let `x` = 1;", "7")
+            .build();
+
+        let md = axtree_to_markdown(&tree);
+        assert_eq!(md, "Run `cargo build` first.\n\n`` let `x` = 1; ``");
+    }
+
+    #[test]
+    fn test_preformatted_code_block() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "code", "1")
+            .text("3", "fn main() {\n    println!(\"hi\");\n}\n", "2")
+            .build();
+
+        let md = axtree_to_markdown(&tree);
+        assert_eq!(md, "```\nfn main() {\n    println!(\"hi\");\n}\n```");
+    }
+
+    #[test]
+    fn test_ordered_list() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "list", "1")
+            .node("3", "listItem", "2")
+            .node("4", "ListMarker", "3")
+            .text("5", "1. ", "4")
+            .text("6", "Preheat the oven", "3")
+            .node("7", "listItem", "2")
+            .node("8", "ListMarker", "7")
+            .text("9", "2. ", "8")
+            .text("10", "Mix the batter", "7")
+            .node("11", "listItem", "2")
+            .node("12", "ListMarker", "11")
+            .text("13", "3. ", "12")
+            .text("14", "Bake", "11")
+            .node("15", "list", "1")
+            .node("16", "listItem", "15")
+            .node("17", "ListMarker", "16")
+            .text("18", "1. ", "17")
+            .text("19", "Serve", "16")
+            .node("20", "list", "1")
+            .node("21", "listItem", "20")
+            .node("22", "ListMarker", "21")
+            .text("23", "• ", "22")
+            .text("24", "Oven mitts", "21")
+            .build();
+
+        let md = axtree_to_markdown(&tree);
+        assert_eq!(md, "1. Preheat the oven\n2. Mix the batter\n3. Bake\n1. Serve\n- Oven mitts");
+
+        let blocks = axtree_to_ast(&tree, &ConvertOptions::default());
+        assert!(matches!(blocks[0], Block::List { ordered: true, .. }));
+        assert!(matches!(blocks[2], Block::List { ordered: false, .. }));
+    }
+
+    #[test]
+    fn test_nested_list() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "list", "1")
+            .node("3", "listItem", "2")
+            .text("4", "Fruit", "3")
+            .node("5", "list", "3")
+            .node("6", "listItem", "5")
+            .text("7", "Apples", "6")
+            .node("8", "listItem", "5")
+            .text("9", "Pears", "8")
+            .node("10", "listItem", "2")
+            .text("11", "Vegetables", "10")
+            .build();
+
+        let md = axtree_to_markdown(&tree);
+        assert_eq!(md, "- Fruit\n  - Apples\n  - Pears\n- Vegetables");
+
+        let mut streamed = Vec::new();
+        axtree_to_markdown_writer(&tree, &ConvertOptions::default(), &mut streamed).unwrap();
+        assert_eq!(String::from_utf8(streamed).unwrap(), md);
+    }
+
+    #[test]
+    fn test_image_without_alt() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "image", "1").str_prop("url", "https://example.com/divider.png")
+            .node("3", "image", "1").name("Team photo").str_prop("url", "https://example.com/team.jpg")
+            .node("4", "image", "1")
+            .build();
+
+        let md = axtree_to_markdown(&tree);
+        assert_eq!(md, "![](https://example.com/divider.png)\n![Team photo](https://example.com/team.jpg)");
+    }
+
+    #[test]
+    fn test_emphasis() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "paragraph", "1")
+            .text("3", "This is ", "2")
+            .node("4", "strong", "2")
+            .text("5", "very", "4")
+            .text("6", " important, ", "2")
+            .node("7", "emphasis", "2")
+            .text("8", "really ", "7")
+            .node("9", "strong", "7")
+            .text("10", "truly", "9")
+            .text("11", ".", "2")
+            .node("12", "paragraph", "1")
+            .node("13", "strong", "12")
+            .text("14", "Bold ", "13")
+            .node("15", "strong", "13")
+            .text("16", "twice", "15")
+            .build();
+
+        let md = axtree_to_markdown(&tree);
+        assert_eq!(md, "This is **very** important, *really **truly***.\n\n**Bold twice**");
+
+
+        let options = ConvertOptions { emphasis_style: EmphasisStyle::Underscore, ..Default::default() };
+        let md = axtree_to_markdown_with_options(&tree, &options);
+        assert!(md.starts_with("This is __very__ important, _really __truly___."));
+
+        // Spaces at the edges of a run move outside its markers
+        let tree = AxTreeBuilder::new()
+            .node("2", "paragraph", "1")
+            .text("3", "This is ", "2")
+            .node("4", "strong", "2")
+            .text("5", "very ", "4")
+            .text("6", "important", "2")
+            .node("7", "emphasis", "2")
+            .text("8", " indeed", "7")
+            .text("9", ".", "2")
+            .build();
+        let md = axtree_to_markdown(&tree);
+        assert_eq!(md, "This is **very** important *indeed*.");
+    }
+
+    #[test]
+    fn test_merge_inline_runs() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "paragraph", "1")
+            .node("3", "strong", "2")
+            .text("4", "Hello", "3")
+            .text("5", " ", "2")
+            .node("6", "strong", "2")
+            .text("7", "world", "6")
+            .text("8", ", said ", "2")
+            .node("9", "emphasis", "2")
+            .text("10", "the", "9")
+            .node("11", "emphasis", "2")
+            .text("12", "program", "11")
+            .text("13", " ", "2")
+            .node("14", "strong", "2")
+            .text("15", "twice", "14")
+            .build();
+
+        let md = axtree_to_markdown(&tree);
+        assert_eq!(md, "**Hello** **world**, said *theprogram* **twice**");
+
+        // Runs only merge with the same style
+        let options = ConvertOptions { merge_inline_runs: true, ..Default::default() };
+        let md = axtree_to_markdown_with_options(&tree, &options);
+        assert_eq!(md, "**Hello world**, said *theprogram* **twice**");
+    }
+
+    #[test]
+    fn test_definition_list() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "list", "1")
+            .node("3", "term", "2")
+            .text("4", "Crate", "3")
+            .node("5", "definition", "2")
+            .text("6", "A compilation unit.", "5")
+            .node("7", "term", "2")
+            .text("8", "Module", "7")
+            .node("9", "definition", "2")
+            .text("10", "A namespace.", "9")
+            .node("11", "definition", "2")
+            .text("12", "Also ", "11")
+            .node("13", "code", "11")
+            .text("14", "mod.rs", "13")
+            .text("15", ".", "11")
+            .node("16", "paragraph", "1")
+            .text("17", "After the list.", "16")
+            .build();
+
+        let md = axtree_to_markdown(&tree);
+        assert_eq!(
+            md,
+            "**Crate**\n: A compilation unit.\n\n**Module**\n: A namespace.\n\n: Also `mod.rs`.\n\nAfter the list."
+        );
+    }
+
+    #[test]
+    fn test_insertion_and_term() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "paragraph", "1")
+            .text("3", "A ", "2")
+            .node("4", "term", "2")
+            .text("5", "crate", "4")
+            .text("6", " is a compilation unit. ", "2")
+            .node("7", "insertion", "2")
+            .text("8", "Updated for 2024.", "7")
+            .build();
+
+        let md = axtree_to_markdown(&tree);
+        assert_eq!(md, "A *crate* is a compilation unit. ++Updated for 2024.++");
+
+        let options = ConvertOptions { insertion_style: InsertionStyle::Html, ..Default::default() };
+        let md = axtree_to_markdown_with_options(&tree, &options);
+        assert_eq!(md, "A *crate* is a compilation unit. <ins>Updated for 2024.</ins>");
+    }
+
+    #[test]
+    fn test_list_item_continuation() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "list", "1")
+            .node("3", "listItem", "2")
+            .node("4", "ListMarker", "3")
+            .text("5", "1. ", "4")
+            .node("6", "paragraph", "3")
+            .text("7", "Install the crate.", "6")
+            .node("8", "paragraph", "3")
+            .text("9", "It needs Rust 1.85.", "8")
+            .node("10", "code", "3")
+            .text("11", "cargo add headful\ncargo build", "10")
+            .node("12", "listItem", "2")
+            .node("13", "ListMarker", "12")
+            .text("14", "2. ", "13")
+            .text("15", "Run it.", "12")
+            .build();
+
+        let md = axtree_to_markdown(&tree);
+        assert_eq!(
+            md,
+            "1. Install the crate.\n\n   It needs Rust 1.85.\n\n   ```\n   cargo add headful\n   cargo build\n   ```\n\n2. Run it."
+        );
+    }
+
+    #[test]
+    fn test_heading_with_linked_logo() {
+        let tree = AxTreeBuilder::new()
+            .node("2", "heading", "1").int_prop("level", 1)
+            .node("3", "link", "2").str_prop("url", "https://example.com/")
+            .node("4", "image", "3").str_prop("alt", "Example Co").str_prop("url", "https://example.com/logo.png")
+            .node("5", "paragraph", "1")
+            .text("6", "Welcome.", "5")
+            .build();
+
+        let md = axtree_to_markdown(&tree);
+        assert_eq!(md, "# [![Example Co](https://example.com/logo.png)](https://example.com/)\n\nWelcome.");
+    }
+
     #[test]
     fn test_real_website() {
         let json = std::fs::read_to_string("./src/test_axt_nodes.json").unwrap();
@@ -0,0 +1,224 @@
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+use chromiumoxide::Page;
+use chromiumoxide::layout::BoundingBox;
+use tokio::time::Instant;
+
+use crate::error::HeadfulError;
+
+/// Kinds of interstitial challenges that block a page's content
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChallengeKind {
+    /// A CAPTCHA the user has to solve
+    Captcha,
+    /// A "checking your browser" interstitial
+    BrowserCheck,
+}
+
+impl fmt::Display for ChallengeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChallengeKind::Captcha => write!(f, "CAPTCHA"),
+            ChallengeKind::BrowserCheck => write!(f, "browser check"),
+        }
+    }
+}
+
+/// Selectors of CAPTCHA widgets: iframes loaded from a provider
+/// (reCAPTCHA, hCaptcha) or the elements widgets are mounted on
+/// (including Turnstile). Invisible reCAPTCHA frames are left out since
+/// scoring (reCAPTCHA v3) loads them on ordinary pages.
+const CAPTCHA_SELECTORS: [&str; 5] = [
+    r#"iframe[src*="recaptcha"]:not([src*="size=invisible"])"#,
+    r#"iframe[src*="hcaptcha"]"#,
+    ".g-recaptcha",
+    ".h-captcha",
+    ".cf-turnstile",
+];
+
+/// Selectors of the containers "checking your browser" interstitials
+/// render into
+const BROWSER_CHECK_SELECTORS: [&str; 4] =
+    ["#challenge-running", "#challenge-stage", "#challenge-form", ".cf-browser-verification"];
+
+/// Lowercased wording of pages that only exist to show a challenge
+const CHALLENGE_PAGE_MARKERS: [&str; 5] = [
+    "not a robot",
+    "unusual traffic",
+    "verify you are human",
+    "checking your browser",
+    "complete the security check",
+];
+
+/// Pages with fewer words than this around a CAPTCHA widget are taken to
+/// be the challenge itself
+const CHALLENGE_PAGE_WORDS: usize = 30;
+
+/// Detect a challenge on a live page from its DOM, with `text` being the
+/// page converted to markdown. Challenges are recognized by their
+/// widgets rather than by wording, so articles that mention captchas or
+/// quote a widget's markup don't trip it. A widget only counts when it
+/// is rendered and covers the page, so forms that embed one (sign-ups,
+/// comments) still convert.
+pub async fn detect(page: &Page, text: &str) -> Result<Option<ChallengeKind>, HeadfulError> {
+    let metrics = page.layout_metrics().await?;
+    let viewport = BoundingBox {
+        x: 0.0,
+        y: 0.0,
+        width: metrics.css_layout_viewport.client_width as f64,
+        height: metrics.css_layout_viewport.client_height as f64,
+    };
+    for selector in CAPTCHA_SELECTORS {
+        for widget in visible_boxes(page, selector).await? {
+            if covers_page(&widget, &viewport, text) {
+                return Ok(Some(ChallengeKind::Captcha));
+            }
+        }
+    }
+    for selector in BROWSER_CHECK_SELECTORS {
+        if visible_boxes(page, selector).await?.iter().any(|check| on_screen(check, &viewport) > 0.0) {
+            return Ok(Some(ChallengeKind::BrowserCheck));
+        }
+    }
+    Ok(None)
+}
+
+/// Boxes of the rendered elements matching a selector. Elements that
+/// aren't rendered (`display: none`, detached) have no box and are left
+/// out.
+async fn visible_boxes(page: &Page, selector: &str) -> Result<Vec<BoundingBox>, HeadfulError> {
+    let mut boxes = Vec::new();
+    for element in page.find_elements(selector).await? {
+        if let Ok(bounds) = element.bounding_box().await
+            && bounds.width > 0.0
+            && bounds.height > 0.0
+        {
+            boxes.push(bounds);
+        }
+    }
+    Ok(boxes)
+}
+
+/// Area of a box that lies within the viewport
+fn on_screen(bounds: &BoundingBox, viewport: &BoundingBox) -> f64 {
+    let width = (bounds.x + bounds.width).min(viewport.x + viewport.width) - bounds.x.max(viewport.x);
+    let height = (bounds.y + bounds.height).min(viewport.y + viewport.height) - bounds.y.max(viewport.y);
+    width.max(0.0) * height.max(0.0)
+}
+
+/// Check whether a widget covers the page: it's on screen and either
+/// fills most of the viewport, like an overlay, or sits on a page that
+/// is only the challenge
+fn covers_page(widget: &BoundingBox, viewport: &BoundingBox, text: &str) -> bool {
+    let area = on_screen(widget, viewport);
+    area > 0.0 && (area * 2.0 >= viewport.width * viewport.height || is_challenge_page(text))
+}
+
+/// Check whether a page's text is only a challenge, by its wording or by
+/// having next to no other content
+fn is_challenge_page(text: &str) -> bool {
+    let text = text.to_lowercase();
+    CHALLENGE_PAGE_MARKERS.iter().any(|marker| text.contains(marker))
+        || text.split_whitespace().count() < CHALLENGE_PAGE_WORDS
+}
+
+/// Decide whether a detected challenge can be handed to the user. Fails
+/// instead of prompting when asked to or when nobody is at the terminal
+/// to complete it.
+pub fn respond(url: &str, kind: ChallengeKind, fail_on_challenge: bool, interactive: bool) -> Result<(), HeadfulError> {
+    if fail_on_challenge || !interactive {
+        return Err(HeadfulError::ChallengeEncountered { url: url.to_string(), kind });
+    }
+    Ok(())
+}
+
+/// Re-check a challenge every `interval` until `cleared` reports it's
+/// gone or `timeout` runs out, without blocking on the terminal. Returns
+/// whether the challenge cleared in time.
+pub async fn poll<F, Fut>(interval: Duration, timeout: Duration, mut cleared: F) -> bool
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = bool>,
+{
+    let started = Instant::now();
+    loop {
+        if cleared().await {
+            return true;
+        }
+        if started.elapsed() + interval > timeout {
+            return false;
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_covers_page() {
+        let viewport = BoundingBox { x: 0.0, y: 0.0, width: 1280.0, height: 720.0 };
+        let checkbox = BoundingBox { x: 488.0, y: 300.0, width: 304.0, height: 78.0 };
+
+        // A widget on a page with little else is the challenge
+        assert!(covers_page(&checkbox, &viewport, "One more step"));
+        assert!(covers_page(&checkbox, &viewport, &format!("{}Please prove you're not a robot", "Words ".repeat(40))));
+
+        // A form that embeds a widget is still a page to convert
+        let text = "# Create your account\n\n".to_string() + &"Tell us a little about yourself. ".repeat(10);
+        assert!(!covers_page(&checkbox, &viewport, &text));
+
+        // Unless the widget is an overlay over the content
+        let overlay = BoundingBox { x: 0.0, y: 0.0, width: 1280.0, height: 720.0 };
+        assert!(covers_page(&overlay, &viewport, &text));
+
+        // Widgets off screen don't cover anything
+        let below = BoundingBox { x: 488.0, y: 2000.0, width: 304.0, height: 78.0 };
+        assert!(!covers_page(&below, &viewport, "One more step"));
+    }
+
+    #[test]
+    fn test_fail_on_challenge() {
+        let url = "https://example.com";
+        let err = respond(url, ChallengeKind::Captcha, true, true).unwrap_err();
+        assert!(matches!(err, HeadfulError::ChallengeEncountered { kind: ChallengeKind::Captcha, .. }));
+        assert_eq!(err.exit_code(), 5);
+        assert_eq!(err.to_string(), "CAPTCHA encountered on https://example.com");
+
+        // Without a terminal there's nobody to solve it
+        assert!(respond(url, ChallengeKind::BrowserCheck, false, false).is_err());
+        assert!(respond(url, ChallengeKind::Captcha, false, true).is_ok());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_poll_interval() {
+        let started = Instant::now();
+        let mut checks = Vec::new();
+        let cleared = poll(Duration::from_millis(1000), Duration::from_secs(5), || {
+            checks.push(started.elapsed());
+            async { false }
+        })
+        .await;
+
+        assert!(!cleared);
+        let expected: Vec<Duration> = (0..=5).map(Duration::from_secs).collect();
+        assert_eq!(checks, expected);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_poll_stops_when_cleared() {
+        let mut checks = 0;
+        let cleared = poll(Duration::from_millis(250), Duration::from_secs(30), || {
+            checks += 1;
+            let done = checks == 3;
+            async move { done }
+        })
+        .await;
+
+        assert!(cleared);
+        assert_eq!(checks, 3);
+    }
+}
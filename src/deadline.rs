@@ -0,0 +1,55 @@
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+/// A wall-clock budget shared by every step of a run
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline {
+    at: Option<Instant>,
+}
+
+impl Deadline {
+    /// Start a deadline that elapses after `budget`, or never if `None`
+    pub fn after(budget: Option<Duration>) -> Self {
+        Deadline {
+            at: budget.map(|budget| Instant::now() + budget),
+        }
+    }
+
+    /// Run a future to completion unless the deadline elapses first, in
+    /// which case the future is dropped and `None` is returned
+    pub async fn run<F: Future>(&self, fut: F) -> Option<F::Output> {
+        match self.at {
+            Some(at) => tokio::time::timeout_at(at.into(), fut).await.ok(),
+            None => Some(fut.await),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_stops_after_deadline_with_partial_results() {
+        let deadline = Deadline::after(Some(Duration::from_millis(100)));
+        let pages = [Duration::ZERO, Duration::from_secs(10), Duration::ZERO];
+
+        let started = Instant::now();
+        let mut completed = Vec::new();
+        for (i, delay) in pages.iter().enumerate() {
+            match deadline.run(tokio::time::sleep(*delay)).await {
+                Some(()) => completed.push(i),
+                None => break,
+            }
+        }
+
+        assert_eq!(completed, vec![0]);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_no_budget_runs_to_completion() {
+        let deadline = Deadline::after(None);
+        assert_eq!(deadline.run(async { 42 }).await, Some(42));
+    }
+}
@@ -0,0 +1,160 @@
+use chromiumoxide::cdp::browser_protocol::emulation::{
+    SetDeviceMetricsOverrideParams, SetTouchEmulationEnabledParams,
+};
+
+/// Viewport, scale and user agent settings for an emulated device
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DevicePreset {
+    pub name: &'static str,
+    pub width: i64,
+    pub height: i64,
+    pub device_scale_factor: f64,
+    pub mobile: bool,
+    pub touch: bool,
+    /// User agent to send, `None` keeps the browser default
+    pub user_agent: Option<&'static str>,
+}
+
+/// Size of the emulated window when no device is emulated. A common
+/// desktop window, so pages lay out the same whether or not Chrome has a
+/// visible window.
+const DEFAULT_WIDTH: i64 = 1280;
+const DEFAULT_HEIGHT: i64 = 800;
+
+/// Size of the emulated window, overriding the device's (or the default
+/// window's) where set
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Viewport {
+    pub width: Option<i64>,
+    pub height: Option<i64>,
+}
+
+impl Viewport {
+    /// CDP parameters emulating the device, or a desktop window without
+    /// one, at this size
+    pub fn metrics_params(&self, device: Option<&DevicePreset>) -> SetDeviceMetricsOverrideParams {
+        let mut params = match device {
+            Some(preset) => preset.metrics_params(),
+            None => SetDeviceMetricsOverrideParams::new(DEFAULT_WIDTH, DEFAULT_HEIGHT, 1.0, false),
+        };
+        params.width = self.width.unwrap_or(params.width);
+        params.height = self.height.unwrap_or(params.height);
+        params
+    }
+}
+
+/// Built-in device presets
+pub const PRESETS: [DevicePreset; 4] = [
+    DevicePreset {
+        name: "iphone-14",
+        width: 390,
+        height: 844,
+        device_scale_factor: 3.0,
+        mobile: true,
+        touch: true,
+        user_agent: Some("Mozilla/5.0 (iPhone; CPU iPhone OS 16_0 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/16.0 Mobile/15E148 Safari/604.1"),
+    },
+    DevicePreset {
+        name: "pixel-7",
+        width: 412,
+        height: 915,
+        device_scale_factor: 2.625,
+        mobile: true,
+        touch: true,
+        user_agent: Some("Mozilla/5.0 (Linux; Android 13; Pixel 7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/116.0.0.0 Mobile Safari/537.36"),
+    },
+    DevicePreset {
+        name: "ipad",
+        width: 820,
+        height: 1180,
+        device_scale_factor: 2.0,
+        mobile: true,
+        touch: true,
+        user_agent: Some("Mozilla/5.0 (iPad; CPU OS 16_0 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/16.0 Mobile/15E148 Safari/604.1"),
+    },
+    DevicePreset {
+        name: "desktop",
+        width: 1920,
+        height: 1080,
+        device_scale_factor: 1.0,
+        mobile: false,
+        touch: false,
+        user_agent: None,
+    },
+];
+
+/// Find a preset by name
+pub fn find(name: &str) -> Option<&'static DevicePreset> {
+    PRESETS.iter().find(|p| p.name == name)
+}
+
+/// Parse a preset name from the command line
+pub fn parse_preset(name: &str) -> Result<&'static DevicePreset, String> {
+    find(name).ok_or_else(|| {
+        let names: Vec<&str> = PRESETS.iter().map(|p| p.name).collect();
+        format!("unknown device `{}`, expected one of: {}", name, names.join(", "))
+    })
+}
+
+impl DevicePreset {
+    /// CDP parameters overriding the device metrics
+    pub fn metrics_params(&self) -> SetDeviceMetricsOverrideParams {
+        SetDeviceMetricsOverrideParams::new(self.width, self.height, self.device_scale_factor, self.mobile)
+    }
+
+    /// CDP parameters toggling touch emulation
+    pub fn touch_params(&self) -> SetTouchEmulationEnabledParams {
+        let mut params = SetTouchEmulationEnabledParams::new(self.touch);
+        if self.touch {
+            params.max_touch_points = Some(5);
+        }
+        params
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preset_metrics() {
+        let preset = parse_preset("iphone-14").unwrap();
+        let metrics = preset.metrics_params();
+        assert_eq!(metrics.width, 390);
+        assert_eq!(metrics.height, 844);
+        assert_eq!(metrics.device_scale_factor, 3.0);
+        assert!(metrics.mobile);
+        assert!(preset.touch_params().enabled);
+        assert!(preset.user_agent.unwrap().contains("iPhone"));
+    }
+
+    #[test]
+    fn test_viewport_metrics() {
+        let metrics = Viewport::default().metrics_params(None);
+        assert_eq!((metrics.width, metrics.height), (1280, 800));
+        assert!(!metrics.mobile);
+
+        let metrics = Viewport { width: Some(390), height: Some(844) }.metrics_params(None);
+        assert_eq!((metrics.width, metrics.height), (390, 844));
+        assert_eq!(metrics.device_scale_factor, 1.0);
+    }
+
+    #[test]
+    fn test_viewport_overrides_device() {
+        let ipad = find("ipad");
+        let metrics = Viewport::default().metrics_params(ipad);
+        assert_eq!((metrics.width, metrics.height), (820, 1180));
+
+        // An explicit size wins, keeping the rest of the preset
+        let metrics = Viewport { width: Some(1180), height: None }.metrics_params(ipad);
+        assert_eq!((metrics.width, metrics.height), (1180, 1180));
+        assert_eq!(metrics.device_scale_factor, 2.0);
+        assert!(metrics.mobile);
+    }
+
+    #[test]
+    fn test_unknown_preset() {
+        let err = parse_preset("nokia-3310").unwrap_err();
+        assert!(err.contains("iphone-14"));
+    }
+}
@@ -0,0 +1,31 @@
+use similar::TextDiff;
+
+/// Produce a unified diff between two conversions, labelled with the
+/// URLs they came from. Returns `None` when they are identical.
+pub fn unified(before: &str, after: &str, before_name: &str, after_name: &str) -> Option<String> {
+    if before == after {
+        return None;
+    }
+    let diff = TextDiff::from_lines(before, after);
+    Some(diff.unified_diff().header(before_name, after_name).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_line_difference() {
+        let before = "# Title\n\nPrice: $10\n\nFooter\n";
+        let after = "# Title\n\nPrice: $12\n\nFooter\n";
+        let diff = unified(before, after, "data:text/html,a", "data:text/html,b").unwrap();
+        assert!(diff.starts_with("--- data:text/html,a\n+++ data:text/html,b\n"));
+        assert!(diff.contains("\n-Price: $10\n+Price: $12\n"));
+        assert_eq!(diff.lines().filter(|l| l.starts_with('-') && !l.starts_with("---")).count(), 1);
+    }
+
+    #[test]
+    fn test_identical() {
+        assert_eq!(unified("same\n", "same\n", "a", "b"), None);
+    }
+}
@@ -0,0 +1,83 @@
+use std::collections::HashSet;
+
+use crate::axtree::{AxNode, AxTree, InternalRoleMap, RoleValueContent};
+
+/// Longest accessible name shown in a node's label
+const MAX_LABEL_NAME: usize = 40;
+
+/// Render the tree as a GraphViz DOT graph for inspecting its structure.
+/// Nodes are labelled with their role and name, and ignored nodes are
+/// dashed and grey. Edges come from both `parentId` and `childIds`, so a
+/// child ID without a matching node shows up as a red edge.
+pub fn render(axtree: &AxTree, roles: &InternalRoleMap) -> String {
+    let mut dot = String::from("digraph axtree {\n  node [shape=box, fontname=\"Helvetica\"];\n");
+    let ids: HashSet<&str> = axtree.nodes.iter().map(|node| node.node_id.as_str()).collect();
+    for node in &axtree.nodes {
+        let style = if axtree.is_ignored(node) { ", style=dashed, fontcolor=gray, color=gray" } else { "" };
+        dot.push_str(&format!("  {} [label={}{}];\n", quote(&node.node_id), quote(&label(node, roles)), style));
+    }
+
+    let parents = axtree.build_parent_map();
+    let mut edges = HashSet::new();
+    for node in &axtree.nodes {
+        let child_ids = node.child_ids.iter().flatten().map(String::as_str);
+        let parented = parents.get(&node.node_id).into_iter().flatten().map(|child| child.node_id.as_str());
+        for child_id in child_ids.chain(parented) {
+            if !edges.insert((node.node_id.as_str(), child_id)) {
+                continue;
+            }
+            let style = if ids.contains(child_id) { "" } else { " [color=red]" };
+            dot.push_str(&format!("  {} -> {}{};\n", quote(&node.node_id), quote(child_id), style));
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// A node's role followed by its name, shortened to fit in a box
+fn label(node: &AxNode, roles: &InternalRoleMap) -> String {
+    let role = match node.role.value {
+        RoleValueContent::Named(ref name) => name.clone(),
+        RoleValueContent::Internal(value) => roles.name(value).map_or_else(|| value.to_string(), String::from),
+        RoleValueContent::Other(ref value) => value.to_string(),
+    };
+    let name = node.name.as_ref().map(|name| name.value.trim()).unwrap_or_default();
+    if name.is_empty() {
+        return role;
+    }
+    let mut short: String = name.chars().take(MAX_LABEL_NAME).collect();
+    if name.chars().count() > MAX_LABEL_NAME {
+        short.push('…');
+    }
+    format!("{}\n{}", role, short)
+}
+
+/// Quote a DOT identifier or label
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::AxTreeBuilder;
+
+    #[test]
+    fn test_render_dot() {
+        let mut tree = AxTreeBuilder::new()
+            .node("2", "heading", "1").name("Say \"hi\"")
+            .node("3", "generic", "1").ignored("uninteresting")
+            .build();
+        tree.nodes[0].child_ids.as_mut().unwrap().push("9".to_string());
+
+        let dot = render(&tree, &InternalRoleMap::default());
+        assert!(dot.starts_with("digraph axtree {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains("  \"1\" [label=\"RootWebArea\"];\n"));
+        assert!(dot.contains("  \"2\" [label=\"heading\\nSay \\\"hi\\\"\"];\n"));
+        assert!(dot.contains("  \"3\" [label=\"generic\", style=dashed, fontcolor=gray, color=gray];\n"));
+        assert!(dot.contains("  \"1\" -> \"2\";\n"));
+        assert!(dot.contains("  \"1\" -> \"9\" [color=red];\n"));
+        assert_eq!(dot.matches("\"1\" -> \"2\"").count(), 1);
+    }
+}
@@ -0,0 +1,160 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use chromiumoxide::error::CdpError;
+
+use crate::challenge::ChallengeKind;
+
+/// Exit code when `--fail-on-diff` finds that the pages differ
+pub const EXIT_DIFFERENT: i32 = 7;
+
+/// Exit code for invalid command line arguments, in place of clap's 2
+/// which timeouts use
+pub const EXIT_USAGE: i32 = 8;
+
+/// Errors that end a run. Each kind exits the process with its own code
+/// so scripts can branch on the failure without parsing stderr.
+#[derive(Debug)]
+pub enum HeadfulError {
+    /// The browser didn't respond in time
+    Timeout(String),
+    /// A page could not be loaded
+    Navigation { url: String, reason: String },
+    /// A page loaded but could not be converted
+    Conversion(String),
+    /// A challenge was detected and couldn't be handed to the user
+    ChallengeEncountered { url: String, kind: ChallengeKind },
+    /// A challenge was still showing after the user was asked to solve it
+    ChallengeUnresolved { url: String },
+    /// The browser could not be started
+    LaunchFailed { reason: String, guidance: Option<String> },
+    /// The command line arguments were invalid or don't go together
+    Usage(String),
+    /// Reading or writing a file or the terminal failed
+    Io(io::Error),
+    /// Anything else, such as a denied host or the browser dropping the
+    /// connection
+    Other(Box<dyn Error + Send + Sync>),
+}
+
+impl HeadfulError {
+    /// The process exit code for this kind of error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            HeadfulError::Other(_) | HeadfulError::Io(_) => 1,
+            HeadfulError::Timeout(_) => 2,
+            HeadfulError::Navigation { .. } => 3,
+            HeadfulError::Conversion(_) => 4,
+            HeadfulError::ChallengeEncountered { .. } | HeadfulError::ChallengeUnresolved { .. } => 5,
+            HeadfulError::LaunchFailed { .. } => 6,
+            HeadfulError::Usage(_) => EXIT_USAGE,
+        }
+    }
+
+    /// Classify a failed navigation, keeping timeouts distinct
+    pub fn navigation(url: &str, err: CdpError) -> Self {
+        match err {
+            CdpError::Timeout => HeadfulError::Timeout(format!("navigating to {}", url)),
+            err => HeadfulError::Navigation { url: url.to_string(), reason: err.to_string() },
+        }
+    }
+}
+
+impl fmt::Display for HeadfulError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeadfulError::Timeout(what) => write!(f, "timed out {}", what),
+            HeadfulError::Navigation { url, reason } => write!(f, "failed to load {}: {}", url, reason),
+            HeadfulError::Conversion(reason) => write!(f, "conversion failed: {}", reason),
+            HeadfulError::ChallengeEncountered { url, kind } => write!(f, "{} encountered on {}", kind, url),
+            HeadfulError::ChallengeUnresolved { url } => write!(f, "challenge on {} was not completed", url),
+            HeadfulError::LaunchFailed { reason, guidance: Some(guidance) } => {
+                write!(f, "failed to launch the browser: {} ({})", reason, guidance)
+            }
+            HeadfulError::LaunchFailed { reason, guidance: None } => {
+                write!(f, "failed to launch the browser: {}", reason)
+            }
+            HeadfulError::Usage(reason) => write!(f, "{}", reason),
+            HeadfulError::Io(err) => write!(f, "{}", err),
+            HeadfulError::Other(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// Every variant that wraps an error prints it as its message, so none
+/// of them report it again as the source
+impl Error for HeadfulError {}
+
+impl From<CdpError> for HeadfulError {
+    fn from(err: CdpError) -> Self {
+        match err {
+            CdpError::Timeout => HeadfulError::Timeout("waiting for the browser".to_string()),
+            err => HeadfulError::Other(Box::new(err)),
+        }
+    }
+}
+
+impl From<serde_json::Error> for HeadfulError {
+    fn from(err: serde_json::Error) -> Self {
+        HeadfulError::Conversion(err.to_string())
+    }
+}
+
+impl From<io::Error> for HeadfulError {
+    fn from(err: io::Error) -> Self {
+        HeadfulError::Io(err)
+    }
+}
+
+impl From<Box<dyn Error + Send + Sync>> for HeadfulError {
+    fn from(err: Box<dyn Error + Send + Sync>) -> Self {
+        HeadfulError::Other(err)
+    }
+}
+
+impl From<String> for HeadfulError {
+    fn from(err: String) -> Self {
+        HeadfulError::Other(err.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeout_exit_code() {
+        let err = HeadfulError::from(CdpError::Timeout);
+        assert_eq!(err.exit_code(), 2);
+
+        let err = HeadfulError::navigation("https://example.com", CdpError::Timeout);
+        assert_eq!(err.exit_code(), 2);
+        assert_eq!(err.to_string(), "timed out navigating to https://example.com");
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        let navigation = HeadfulError::navigation(
+            "https://example.invalid",
+            CdpError::ChromeMessage("net::ERR_NAME_NOT_RESOLVED".to_string()),
+        );
+        assert_eq!(navigation.exit_code(), 3);
+        assert_eq!(HeadfulError::Conversion("bad tree".to_string()).exit_code(), 4);
+        assert_eq!(HeadfulError::ChallengeUnresolved { url: "https://example.com".to_string() }.exit_code(), 5);
+        let launch = HeadfulError::LaunchFailed { reason: "no chrome".to_string(), guidance: None };
+        assert_eq!(launch.exit_code(), 6);
+        assert_eq!(HeadfulError::Usage("--diff compares a single URL".to_string()).exit_code(), 8);
+        assert_eq!(HeadfulError::from("host denied".to_string()).exit_code(), 1);
+    }
+
+    #[test]
+    fn test_io_errors_keep_their_kind() {
+        let err = HeadfulError::from(io::Error::new(io::ErrorKind::PermissionDenied, "out.md"));
+        assert!(matches!(err, HeadfulError::Io(ref e) if e.kind() == io::ErrorKind::PermissionDenied));
+        assert_eq!(err.exit_code(), 1);
+        assert_eq!(err.to_string(), "out.md");
+        // Reporters that walk the chain would print the message twice
+        assert!(err.source().is_none());
+    }
+}
@@ -0,0 +1,957 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chromiumoxide::cdp::browser_protocol::dom::{BackendNodeId, GetBoxModelParams};
+use chromiumoxide::cdp::browser_protocol::network::ResourceType;
+use chromiumoxide::cdp::browser_protocol::page::{
+    AddScriptToEvaluateOnNewDocumentParams, FrameId, GetResourceContentParams, GetResourceTreeParams,
+};
+use chromiumoxide::{Command, Method, Page, browser::Browser};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::task::{self, JoinHandle};
+
+use crate::error::HeadfulError;
+use crate::{axtree, browser, challenge, device, dot, headers, html, lang, links, pagination, style};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GetFullAxTree;
+
+impl Command for GetFullAxTree {
+    type Response = Value;
+}
+
+impl Method for GetFullAxTree {
+    fn identifier(&self) -> chromiumoxide::types::MethodId {
+        chromiumoxide::types::MethodId::Borrowed("Accessibility.getFullAXTree")
+    }
+}
+
+/// Settings for fetching a page and converting it to markdown
+#[derive(Debug, Clone)]
+pub struct FetchConfig {
+    /// Convert using the accessibility tree instead of the HTML
+    pub axtree: bool,
+    /// Emit the JSON tree of structural blocks instead of markdown. This
+    /// uses the accessibility tree
+    pub ast: bool,
+    /// Emit the raw accessibility tree as JSON instead of converting it
+    pub raw_tree: bool,
+    /// Emit the accessibility tree as a GraphViz DOT graph instead of
+    /// converting it
+    pub dot: bool,
+    /// Chrome binary to launch, detected automatically when `None`
+    pub chrome_path: Option<PathBuf>,
+    /// Launch the browser without a window. Challenges can't be solved
+    /// interactively so they fail instead
+    pub headless: bool,
+    /// Running Chrome DevTools endpoint to connect to instead of
+    /// launching a new browser
+    pub connect: Option<String>,
+    /// Proxy server to route the launched browser's traffic through
+    pub proxy: Option<String>,
+    /// Device to emulate while loading the page
+    pub device: Option<&'static device::DevicePreset>,
+    /// Window size to emulate, taking precedence over the device's
+    pub viewport: device::Viewport,
+    /// User agent to send instead of Chromium's own, taking precedence
+    /// over the device's
+    pub user_agent: Option<String>,
+    /// Language to request content in, e.g. `fr-FR`
+    pub lang: Option<String>,
+    /// CSS selector of an element to wait for after navigating, for pages
+    /// that render their content after loading
+    pub wait_for: Option<String>,
+    /// Fixed time to wait after the page loads (and any `wait_for`
+    /// element appears) before capturing it, letting late scripts settle
+    pub delay_before_capture: Option<Duration>,
+    /// JavaScript run at the start of every document the page loads,
+    /// including subframes, before the page's own scripts
+    pub user_script: Option<String>,
+    /// Fail instead of prompting when a CAPTCHA or browser check is
+    /// detected. Always the case when stdin isn't a terminal
+    pub fail_on_challenge: bool,
+    /// Wait up to this long for a challenge to clear by re-checking the
+    /// page, instead of asking the user to press return
+    pub captcha_timeout: Option<Duration>,
+    /// How often the page is re-checked while waiting for a challenge to
+    /// clear
+    pub captcha_poll_interval: Duration,
+    /// Capture the page's HTTP response headers
+    pub dump_headers: bool,
+    /// Keep cookies and authorization headers instead of redacting them
+    pub include_sensitive_headers: bool,
+    /// Embed images as base64 data URIs (axtree mode)
+    pub inline_images: bool,
+    /// Skip inlining images larger than this many bytes
+    pub max_image_bytes: usize,
+    /// Keep HTML entities such as `&amp;` literally in the output
+    pub raw_entities: bool,
+    /// Fail when the accessibility tree has dangling child references
+    pub strict_tree: bool,
+    /// Fail when the accessibility tree has nothing to convert
+    pub fail_on_empty: bool,
+    /// Selector (or `rel=next`) of the next-page link to follow, appending
+    /// each following page to the first
+    pub follow_next: Option<String>,
+    /// Most pages to load when following next-page links
+    pub max_pages: usize,
+    /// Hosts next-page links may lead to, any host when `None`
+    pub host_filter: Option<links::HostFilter>,
+    /// Capture the accessibility tree again after this long and keep
+    /// only the content added in between
+    pub new_after: Option<Duration>,
+    /// CSS selectors of elements to remove from the accessibility tree,
+    /// along with their contents
+    pub exclude_selectors: Vec<String>,
+    /// Markers used for italic and bold text in HTML mode
+    pub emphasis_style: style::EmphasisStyle,
+    /// Characters used for horizontal rules in HTML mode
+    pub hr_style: style::HrStyle,
+    /// Elements dropped before conversion in HTML mode
+    pub skip_tags: Vec<String>,
+    /// Accessibility tree conversion options. Inlined images and node
+    /// geometry are filled in from the page
+    pub convert: axtree::ConvertOptions,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        FetchConfig {
+            axtree: false,
+            ast: false,
+            raw_tree: false,
+            dot: false,
+            chrome_path: None,
+            headless: false,
+            connect: None,
+            proxy: None,
+            device: None,
+            viewport: device::Viewport::default(),
+            user_agent: None,
+            lang: None,
+            user_script: None,
+            wait_for: None,
+            delay_before_capture: None,
+            fail_on_challenge: false,
+            captcha_timeout: None,
+            captcha_poll_interval: Duration::from_millis(1000),
+            dump_headers: false,
+            include_sensitive_headers: false,
+            inline_images: false,
+            max_image_bytes: 1_000_000,
+            raw_entities: false,
+            strict_tree: false,
+            fail_on_empty: false,
+            follow_next: None,
+            max_pages: 10,
+            host_filter: None,
+            new_after: None,
+            exclude_selectors: Vec::new(),
+            emphasis_style: style::EmphasisStyle::default(),
+            hr_style: style::HrStyle::default(),
+            skip_tags: html::skip_tags(&[], &[]),
+            convert: axtree::ConvertOptions::default(),
+        }
+    }
+}
+
+impl FetchConfig {
+    /// Whether the accessibility tree has to be captured, for converting
+    /// it or emitting it in another format
+    fn needs_tree(&self) -> bool {
+        self.axtree || self.ast || self.raw_tree || self.dot
+    }
+
+    /// Whether pages are converted to JSON instead of markdown
+    pub fn emits_json(&self) -> bool {
+        self.ast || self.raw_tree
+    }
+}
+
+/// A launched (or connected) browser and the task driving it
+pub struct Session {
+    pub browser: Browser,
+    handle: JoinHandle<()>,
+    connected: bool,
+}
+
+impl Session {
+    /// Launch a browser, or connect to a running one when the
+    /// config has an endpoint
+    pub async fn start(config: &FetchConfig) -> Result<Self, HeadfulError> {
+        let plan = browser::LaunchPlan {
+            executable: config.chrome_path.clone(),
+            args: config.proxy.as_deref().map(browser::proxy_arg).into_iter().collect(),
+            headless: config.headless,
+        };
+        let (browser, mut handler) = browser::start(config.connect.as_deref(), plan).await?;
+        let handle = task::spawn(async move {
+            while let Some(h) = handler.next().await {
+                if h.is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Session { browser, handle, connected: config.connect.is_some() })
+    }
+
+    /// Close the browser, leaving one we connected to running for its
+    /// owner
+    pub async fn close(mut self) -> Result<(), HeadfulError> {
+        if self.connected {
+            self.handle.abort();
+        } else {
+            self.browser.close().await?;
+            let _ = self.handle.await;
+        }
+        Ok(())
+    }
+}
+
+/// Everything captured from a loaded page that conversion needs
+#[derive(Default)]
+pub struct Capture {
+    pub html: String,
+    pub axt: Option<axtree::AxTree>,
+    pub title: Option<String>,
+    /// The page's preview image from its `og:image` or `twitter:image`
+    /// meta tag
+    pub image: Option<String>,
+    pub status: Option<i64>,
+    /// Response headers of the main document, with `dump_headers`
+    pub headers: Option<BTreeMap<String, String>>,
+    /// The challenge shown before the content, if one had to be solved
+    pub challenge: Option<challenge::ChallengeKind>,
+    pub inline_images: HashMap<String, String>,
+    pub geometry: HashMap<i64, axtree::BoundingBox>,
+    /// Pages reached by following next-page links, in order
+    pub continued: Vec<Capture>,
+    /// Problems that didn't stop the capture, such as images that
+    /// couldn't be inlined, for the caller to report
+    pub warnings: Vec<String>,
+}
+
+fn wait_for_enter(prompt: &str) -> io::Result<()> {
+    print!("{prompt}");
+    io::stdout().flush()?;          // Propagate any flushing error
+    let mut line = String::new();
+    io::stdin().read_line(&mut line)?; // Propagate read errors
+    Ok(())
+}
+
+/// Images the page has loaded, by URL, with the frame that loaded each
+/// and its MIME type
+async fn loaded_images(page: &Page) -> HashMap<String, (FrameId, String)> {
+    let Ok(resp) = page.execute(GetResourceTreeParams::default()).await else {
+        return HashMap::new();
+    };
+    let mut images = HashMap::new();
+    let mut frames = vec![resp.result.frame_tree];
+    while let Some(tree) = frames.pop() {
+        for resource in tree.resources {
+            if resource.r#type == ResourceType::Image {
+                images.entry(resource.url).or_insert_with(|| (tree.frame.id.clone(), resource.mime_type));
+            }
+        }
+        frames.extend(tree.child_frames.unwrap_or_default());
+    }
+    images
+}
+
+/// Read an image the page loaded out of the browser's cache and return
+/// it as a base64 data URI. Reading it over DevTools instead of fetching
+/// it from the page keeps cross-origin images from being blocked by
+/// CORS. Returns `None` when the page didn't load the image or it's
+/// larger than `max_bytes`.
+async fn fetch_image_data_uri(
+    page: &Page,
+    images: &HashMap<String, (FrameId, String)>,
+    url: &str,
+    max_bytes: usize,
+) -> Option<String> {
+    let (frame_id, mime_type) = images.get(url)?;
+    let resp = page.execute(GetResourceContentParams::new(frame_id.clone(), url)).await.ok()?;
+    image_data_uri(mime_type, &resp.result.content, resp.result.base64_encoded, max_bytes)
+}
+
+/// Build a data URI from resource content as DevTools returns it, base64
+/// encoded already or as text. Returns `None` when the decoded image is
+/// larger than `max_bytes`.
+fn image_data_uri(mime_type: &str, content: &str, base64_encoded: bool, max_bytes: usize) -> Option<String> {
+    let (size, encoded) = if base64_encoded {
+        let padding = content.bytes().rev().take_while(|b| *b == b'=').count();
+        ((content.len() / 4 * 3).saturating_sub(padding), Cow::Borrowed(content))
+    } else {
+        (content.len(), Cow::Owned(BASE64.encode(content)))
+    };
+    (size <= max_bytes).then(|| format!("data:{};base64,{}", mime_type, encoded))
+}
+
+/// Find the page's preview image, preferring `og:image` over
+/// `twitter:image`. Relative URLs are resolved against the page.
+async fn preview_image(page: &Page) -> Option<String> {
+    let script = r#"(() => {
+        const meta = document.querySelector('meta[property="og:image"], meta[name="og:image"]')
+            || document.querySelector('meta[name="twitter:image"], meta[property="twitter:image"]');
+        const content = meta && meta.content.trim();
+        if (!content) return null;
+        try {
+            return new URL(content, document.baseURI).href;
+        } catch (e) {
+            return content;
+        }
+    })()"#;
+    page.evaluate(script).await.ok()?.into_value::<Option<String>>().ok()?
+}
+
+/// Capture the layout bounds of every node in the tree that maps to a
+/// DOM node. Nodes without a box model (e.g. not rendered) are skipped.
+async fn capture_geometry(page: &Page, axt: &axtree::AxTree) -> HashMap<i64, axtree::BoundingBox> {
+    let mut geometry = HashMap::new();
+    for backend_id in axt.nodes.iter().filter_map(|n| n.backend_dom_node_id) {
+        if geometry.contains_key(&backend_id) {
+            continue;
+        }
+        let params = GetBoxModelParams::builder()
+            .backend_node_id(BackendNodeId::new(backend_id))
+            .build();
+        let Ok(resp) = page.execute(params).await else {
+            continue;
+        };
+        let quad = resp.result.model.border.inner();
+        let x = quad.iter().step_by(2).cloned().fold(f64::INFINITY, f64::min);
+        let y = quad.iter().skip(1).step_by(2).cloned().fold(f64::INFINITY, f64::min);
+        if x.is_finite() && y.is_finite() {
+            geometry.insert(backend_id, axtree::BoundingBox {
+                x,
+                y,
+                width: resp.result.model.width as f64,
+                height: resp.result.model.height as f64,
+            });
+        }
+    }
+    geometry
+}
+
+/// Open a new page, navigate to the URL and capture its content, along
+/// with the pages after it when following next-page links
+pub async fn capture_page(browser: &Browser, config: &FetchConfig, url: &str) -> Result<Capture, HeadfulError> {
+    let hosts = config.host_filter.as_ref();
+    let mut pages = pagination::follow(url, config.max_pages.max(1), hosts, |url| async move {
+        capture_one(browser, config, &url).await
+    }).await?;
+    let mut capture = pages.remove(0);
+    capture.continued = pages;
+    Ok(capture)
+}
+
+/// How long chromiumoxide waits on a navigation, also the limit for
+/// `wait_for` elements to appear
+const NAVIGATION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often to look for a `wait_for` element
+const WAIT_FOR_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Wait for an element matching the selector to be added to the page
+async fn wait_for_selector(page: &Page, url: &str, selector: &str) -> Result<(), HeadfulError> {
+    let found = challenge::poll(WAIT_FOR_INTERVAL, NAVIGATION_TIMEOUT, || async {
+        page.find_element(selector).await.is_ok()
+    })
+    .await;
+    if !found {
+        return Err(HeadfulError::Timeout(format!("waiting for {} on {}", selector, url)));
+    }
+    Ok(())
+}
+
+/// Open a blank page set up with the config's emulation, language and
+/// user script, ready to navigate. The page is closed again when it
+/// can't be set up.
+async fn prepare_page(browser: &Browser, config: &FetchConfig) -> Result<Page, HeadfulError> {
+    let page = browser.new_page("about:blank").await?;
+    match set_up_page(&page, config).await {
+        Ok(()) => Ok(page),
+        Err(e) => {
+            let _ = page.close().await;
+            Err(e)
+        }
+    }
+}
+
+/// Apply the config's emulation, language and user script to a page
+async fn set_up_page(page: &Page, config: &FetchConfig) -> Result<(), HeadfulError> {
+    // Apply device emulation before navigating so the first request
+    // already looks like the device
+    page.execute(config.viewport.metrics_params(config.device)).await?;
+    if let Some(preset) = config.device {
+        page.execute(preset.touch_params()).await?;
+    }
+
+    // A custom user agent wins over the device's
+    let user_agent = config.user_agent.as_deref()
+        .or(config.device.and_then(|preset| preset.user_agent));
+    if let Some(user_agent) = user_agent {
+        page.set_user_agent(user_agent).await?;
+    }
+
+    // Ask for localized content, keeping any custom or device user agent
+    if let Some(ref tag) = config.lang {
+        let user_agent = match user_agent {
+            Some(user_agent) => user_agent.to_string(),
+            None => page.user_agent().await?,
+        };
+        page.execute(lang::header_params(tag)).await?;
+        page.set_user_agent(lang::emulation_params(tag, &user_agent)).await?;
+    }
+    // Install the user script before navigating so it runs before any of
+    // the page's scripts
+    if let Some(ref source) = config.user_script {
+        page.execute(AddScriptToEvaluateOnNewDocumentParams::new(source.clone())).await?;
+    }
+    Ok(())
+}
+
+/// Capture a single page, returning the URL of the next page when
+/// following next-page links
+async fn capture_one(
+    browser: &Browser,
+    config: &FetchConfig,
+    url: &str,
+) -> Result<(Capture, Option<String>), HeadfulError> {
+    // Close the page whether or not capturing worked, so failed pages
+    // don't pile up as tabs in a shared browser
+    let page = prepare_page(browser, config).await?;
+    let captured = capture_open_page(&page, config, url).await;
+    let closed = page.close().await;
+    let captured = captured?;
+    closed?;
+    Ok(captured)
+}
+
+/// Navigate an open page to the URL and capture it
+async fn capture_open_page(
+    page: &Page,
+    config: &FetchConfig,
+    url: &str,
+) -> Result<(Capture, Option<String>), HeadfulError> {
+    page.goto(url).await.map_err(|e| HeadfulError::navigation(url, e))?;
+    let response = page.wait_for_navigation_response().await?
+        .and_then(|request| request.response.clone());
+    let status = response.as_ref().map(|response| response.status);
+    let headers = match response {
+        Some(response) if config.dump_headers => {
+            Some(headers::collect(response.headers.inner(), config.include_sensitive_headers))
+        }
+        _ => None,
+    };
+    if let Some(ref selector) = config.wait_for {
+        wait_for_selector(page, url, selector).await?;
+    }
+    if let Some(delay) = config.delay_before_capture {
+        tokio::time::sleep(delay).await;
+    }
+    let mut html = page.content().await?;
+
+    // Detect challenges from the page's DOM and wait for the user to
+    // complete them, unless nobody is there to do so
+    let converter = html::converter(config.emphasis_style, config.hr_style, &config.skip_tags);
+    let detected = challenge::detect(page, &html::convert(&converter, &html)).await?;
+    if let Some(kind) = detected {
+        let fail = config.fail_on_challenge || config.headless;
+        // Polling only needs someone at the browser window, not the terminal
+        let interactive = io::stdin().is_terminal() || config.captcha_timeout.is_some();
+        challenge::respond(url, kind, fail, interactive)?;
+        if let Some(timeout) = config.captcha_timeout {
+            eprintln!("Please complete the {} in the browser, waiting up to {}s", kind, timeout.as_secs());
+            let cleared = challenge::poll(config.captcha_poll_interval, timeout, || async {
+                // Errors while the page navigates count as not cleared yet
+                match page.content().await {
+                    Ok(html) => {
+                        let text = html::convert(&converter, &html);
+                        matches!(challenge::detect(page, &text).await, Ok(None))
+                    }
+                    Err(_) => false,
+                }
+            })
+            .await;
+            if !cleared {
+                return Err(HeadfulError::ChallengeUnresolved { url: url.to_string() });
+            }
+            html = page.content().await?;
+        } else {
+            // This is blocking!
+            wait_for_enter(&format!("Please complete the {} and press return to continue", kind))?;
+            html = page.wait_for_navigation().await?.content().await?;
+            if challenge::detect(page, &html::convert(&converter, &html)).await?.is_some() {
+                return Err(HeadfulError::ChallengeUnresolved { url: url.to_string() });
+            }
+        }
+    }
+
+    let axt_value = page.execute(GetFullAxTree).await?;
+    let title = page.get_title().await?;
+    let image = preview_image(page).await;
+
+    // Parse the accessibility tree from JSON value
+    let mut axt: Option<axtree::AxTree> = if config.needs_tree() {
+        let axt_json = serde_json::to_string(&axt_value.result)?;
+        Some(serde_json::from_str(&axt_json)?)
+    } else {
+        None
+    };
+
+    // Snapshot the tree again once dynamic content had time to load and
+    // keep only what was added
+    if let Some(ref before) = axt
+        && let Some(wait) = config.new_after
+    {
+        tokio::time::sleep(wait).await;
+        let axt_value = page.execute(GetFullAxTree).await?;
+        let after: axtree::AxTree = serde_json::from_value(axt_value.result.clone())?;
+        axt = Some(after.added_since(before));
+    }
+
+    // Drop the subtrees of elements matching the exclude selectors
+    if let Some(ref tree) = axt
+        && !config.exclude_selectors.is_empty()
+    {
+        let mut excluded = HashSet::new();
+        for selector in &config.exclude_selectors {
+            for element in page.find_elements(selector.as_str()).await? {
+                excluded.insert(*element.backend_node_id.inner());
+            }
+        }
+        axt = Some(tree.without_dom_nodes(&excluded));
+    }
+
+    // Fetch images while the page is still open so they can be embedded
+    // in the output
+    let mut warnings = axt.as_ref().map(|axt| tree_warnings(config, axt)).unwrap_or_default();
+    let mut inline_images = HashMap::new();
+    if let Some(ref axt) = axt
+        && config.inline_images
+    {
+        let images = loaded_images(page).await;
+        for url in axt.image_urls() {
+            match fetch_image_data_uri(page, &images, &url, config.max_image_bytes).await {
+                Some(data_uri) => {
+                    inline_images.insert(url, data_uri);
+                }
+                None => warnings.push(format!("Skipping image {} (not loaded or too large)", url)),
+            }
+        }
+    }
+
+    // Capture node geometry for column reordering
+    let geometry = match axt {
+        Some(ref axt) if config.convert.columns.is_some() => capture_geometry(page, axt).await,
+        _ => HashMap::new(),
+    };
+
+    let next_url = match config.follow_next {
+        Some(ref selector) => {
+            let script = pagination::next_link_script(&pagination::next_selector(selector))?;
+            page.evaluate(script).await?.into_value::<Option<String>>()?
+        }
+        None => None,
+    };
+
+    let capture = Capture {
+        html,
+        axt,
+        title,
+        image,
+        status,
+        headers,
+        challenge: detected,
+        inline_images,
+        geometry,
+        warnings,
+        ..Default::default()
+    };
+    Ok((capture, next_url))
+}
+
+/// Open a page and navigate to the URL, leaving it open to be explored
+/// with the REPL
+pub async fn open_live_page(browser: &Browser, config: &FetchConfig, url: &str) -> Result<Page, HeadfulError> {
+    let page = prepare_page(browser, config).await?;
+    let loaded = async {
+        page.goto(url).await.map_err(|e| HeadfulError::navigation(url, e))?;
+        page.wait_for_navigation().await?;
+        if let Some(ref selector) = config.wait_for {
+            wait_for_selector(&page, url, selector).await?;
+        }
+        Ok(())
+    }
+    .await;
+    match loaded {
+        Ok(()) => Ok(page),
+        Err(e) => {
+            let _ = page.close().await;
+            Err(e)
+        }
+    }
+}
+
+/// Convert the current state of an open page
+pub async fn live_markdown(page: &Page, config: &FetchConfig) -> Result<String, HeadfulError> {
+    let axt = if config.needs_tree() {
+        let axt_value = page.execute(GetFullAxTree).await?;
+        Some(serde_json::from_value(axt_value.result.clone())?)
+    } else {
+        None
+    };
+    let capture = Capture { html: page.content().await?, axt, ..Default::default() };
+    convert_capture(config, capture)
+}
+
+/// Convert the HTML of the elements on an open page matching a selector
+pub async fn live_selector_markdown(page: &Page, config: &FetchConfig, selector: &str) -> Result<String, HeadfulError> {
+    let converter = html::converter(config.emphasis_style, config.hr_style, &config.skip_tags);
+    let mut blocks = Vec::new();
+    for element in page.find_elements(selector).await? {
+        if let Some(html) = element.outer_html().await? {
+            blocks.push(html::convert(&converter, &html));
+        }
+    }
+    if blocks.is_empty() {
+        return Err(format!("No element matches {}", selector).into());
+    }
+    Ok(blocks.join("\n\n"))
+}
+
+/// Accessibility tree conversion options for a captured page
+pub fn convert_options(config: &FetchConfig, capture: &mut Capture) -> axtree::ConvertOptions {
+    axtree::ConvertOptions {
+        inline_images: std::mem::take(&mut capture.inline_images),
+        geometry: std::mem::take(&mut capture.geometry),
+        ..config.convert.clone()
+    }
+}
+
+/// Check the accessibility tree for dangling child references. The
+/// references themselves are listed by `tree_warnings`.
+pub fn check_tree(axt: &axtree::AxTree) -> Result<(), HeadfulError> {
+    let missing = axt.missing_children();
+    if !missing.is_empty() {
+        return Err(HeadfulError::Conversion(format!(
+            "accessibility tree has {} dangling child references",
+            missing.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Fail with `fail_on_empty` when the accessibility tree has nothing to
+/// convert. Without it `tree_warnings` reports the tree instead.
+pub fn check_empty(config: &FetchConfig, axt: &axtree::AxTree) -> Result<(), HeadfulError> {
+    match axt.emptiness() {
+        Some(empty) if config.fail_on_empty => Err(HeadfulError::Conversion(empty.to_string())),
+        _ => Ok(()),
+    }
+}
+
+/// Warnings about a captured tree: its dangling child references with
+/// `strict_tree`, and having nothing to convert unless that fails the
+/// page
+pub fn tree_warnings(config: &FetchConfig, axt: &axtree::AxTree) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if config.strict_tree {
+        warnings.extend(axt.missing_children().iter().map(ToString::to_string));
+    }
+    if !config.fail_on_empty
+        && let Some(empty) = axt.emptiness()
+    {
+        warnings.push(empty.to_string());
+    }
+    warnings
+}
+
+/// Convert a captured page using the accessibility tree or HTML, followed
+/// by any pages reached through next-page links. Markdown pages and DOT
+/// graphs follow one another; JSON output becomes an array with an entry
+/// per page when following next-page links, so it stays one document.
+pub fn convert_capture(config: &FetchConfig, mut capture: Capture) -> Result<String, HeadfulError> {
+    let continued = std::mem::take(&mut capture.continued);
+    if config.follow_next.is_some() && config.emits_json() {
+        let pages = std::iter::once(capture)
+            .chain(continued)
+            .map(|page| tree_json(config, page))
+            .collect::<Result<Vec<Value>, HeadfulError>>()?;
+        return Ok(serde_json::to_string_pretty(&pages)?);
+    }
+
+    let mut markdown = convert_single(config, capture)?;
+    for page in continued {
+        markdown.push_str("\n\n");
+        markdown.push_str(&convert_single(config, page)?);
+    }
+    Ok(markdown)
+}
+
+/// Convert one captured page
+fn convert_single(config: &FetchConfig, mut capture: Capture) -> Result<String, HeadfulError> {
+    if config.emits_json() && capture.axt.is_some() {
+        return Ok(serde_json::to_string_pretty(&tree_json(config, capture)?)?);
+    }
+    let Some(axt) = capture.axt.take() else {
+        let converter = html::converter(config.emphasis_style, config.hr_style, &config.skip_tags);
+        if config.raw_entities {
+            capture.html = html::preserve_entities(&capture.html);
+        }
+        return Ok(html::convert(&converter, &capture.html));
+    };
+    if config.dot {
+        return Ok(dot::render(&axt, &config.convert.internal_roles));
+    }
+
+    check_convertible(config, &axt)?;
+    let options = convert_options(config, &mut capture);
+    Ok(axtree::axtree_to_markdown_with_options(&axt, &options))
+}
+
+/// The JSON emitted for a captured page: its raw accessibility tree, or
+/// the tree of blocks converted from it with `ast`
+fn tree_json(config: &FetchConfig, mut capture: Capture) -> Result<Value, HeadfulError> {
+    let Some(axt) = capture.axt.take() else {
+        return Err(HeadfulError::Conversion("no accessibility tree was captured".to_string()));
+    };
+    if config.raw_tree {
+        return Ok(serde_json::to_value(&axt)?);
+    }
+    check_convertible(config, &axt)?;
+    let options = convert_options(config, &mut capture);
+    Ok(serde_json::to_value(axtree::axtree_to_ast(&axt, &options))?)
+}
+
+/// Check a tree before converting it, as asked to by `strict_tree` and
+/// `fail_on_empty`
+fn check_convertible(config: &FetchConfig, axt: &axtree::AxTree) -> Result<(), HeadfulError> {
+    if config.strict_tree {
+        check_tree(axt)?;
+    }
+    check_empty(config, axt)
+}
+
+/// Launch a browser, load the URL and convert it to markdown, closing the
+/// browser when done
+pub async fn fetch_markdown(url: &str, config: &FetchConfig) -> Result<String, HeadfulError> {
+    let session = Session::start(config).await?;
+    let capture = capture_page(&session.browser, config, url).await;
+    session.close().await?;
+    convert_capture(config, capture?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::AxTreeBuilder;
+
+    #[tokio::test]
+    async fn test_fetch_markdown_reports_connect_failure() {
+        // Nothing listens on a port that was just released
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let config = FetchConfig { connect: Some(endpoint.clone()), ..Default::default() };
+        let err = fetch_markdown("https://example.com", &config).await.unwrap_err();
+        assert!(err.to_string().starts_with(&format!("Failed to connect to {}", endpoint)));
+    }
+
+    #[test]
+    fn test_fail_on_empty_tree() {
+        let capture = |axt| Capture { axt: Some(axt), ..Default::default() };
+        let config = FetchConfig { axtree: true, ..Default::default() };
+        assert_eq!(convert_capture(&config, capture(axtree::AxTree { nodes: Vec::new() })).unwrap(), "");
+
+        let config = FetchConfig { axtree: true, fail_on_empty: true, ..Default::default() };
+        let err = convert_capture(&config, capture(axtree::AxTree { nodes: Vec::new() })).unwrap_err();
+        assert_eq!(err.to_string(), "conversion failed: accessibility tree has no nodes");
+        assert_eq!(err.exit_code(), 4);
+    }
+
+    #[test]
+    fn test_tree_warnings() {
+        let empty = axtree::AxTree { nodes: Vec::new() };
+        let config = FetchConfig { axtree: true, ..Default::default() };
+        assert_eq!(tree_warnings(&config, &empty), vec!["accessibility tree has no nodes"]);
+
+        // Failing the page replaces the warning
+        let config = FetchConfig { fail_on_empty: true, ..config };
+        assert!(tree_warnings(&config, &empty).is_empty());
+
+        let mut dangling = AxTreeBuilder::new().node("2", "paragraph", "1").text("3", "Text", "2").build();
+        dangling.nodes[1].child_ids.as_mut().unwrap().push("99".to_string());
+        let config = FetchConfig { strict_tree: true, ..Default::default() };
+        let warnings = tree_warnings(&config, &dangling);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("99"));
+    }
+
+    #[test]
+    fn test_followed_pages_stay_one_json_document() {
+        let page = |text: &str| Capture {
+            axt: Some(AxTreeBuilder::new().node("2", "paragraph", "1").text("3", text, "2").build()),
+            ..Default::default()
+        };
+        let capture = Capture { continued: vec![page("Part two")], ..page("Part one") };
+        let config = FetchConfig { ast: true, follow_next: Some("rel=next".to_string()), ..Default::default() };
+        let json: Value = serde_json::from_str(&convert_capture(&config, capture).unwrap()).unwrap();
+        assert_eq!(json[0][0]["text"], "Part one");
+        assert_eq!(json[1][0]["text"], "Part two");
+    }
+
+    #[test]
+    fn test_image_data_uri() {
+        // Binary images come back base64 encoded already
+        let gif = "R0lGODlhAQABAAAAACw=";
+        assert_eq!(image_data_uri("image/gif", gif, true, 1_000).unwrap(), format!("data:image/gif;base64,{}", gif));
+
+        // Text images such as SVG are encoded here
+        let svg = "<svg xmlns=\"http://www.w3.org/2000/svg\"/>";
+        let data_uri = image_data_uri("image/svg+xml", svg, false, 1_000).unwrap();
+        let encoded = data_uri.strip_prefix("data:image/svg+xml;base64,").unwrap();
+        assert_eq!(BASE64.decode(encoded).unwrap(), svg.as_bytes());
+    }
+
+    #[test]
+    fn test_image_size_limit() {
+        // 14 bytes once decoded, not the 20 characters of its encoding
+        let gif = "R0lGODlhAQABAAAAACw=";
+        assert_eq!(BASE64.decode(gif).unwrap().len(), 14);
+        assert!(image_data_uri("image/gif", gif, true, 14).is_some());
+        assert!(image_data_uri("image/gif", gif, true, 13).is_none());
+
+        let svg = "<svg/>";
+        assert!(image_data_uri("image/svg+xml", svg, false, 6).is_some());
+        assert!(image_data_uri("image/svg+xml", svg, false, 5).is_none());
+    }
+
+    #[test]
+    fn test_fetch_markdown_is_send() {
+        // Checked at compile time, so the future can be spawned on a
+        // multi-threaded runtime. It's never polled.
+        fn assert_send<T: Send>(_: &T) {}
+        let config = FetchConfig::default();
+        let future = fetch_markdown("https://example.com", &config);
+        assert_send(&future);
+    }
+
+    #[tokio::test]
+    #[ignore = "launches Chrome"]
+    async fn test_user_script_runs_before_page() {
+        let script = r#"
+            window.injected = "Injected by user script";
+            document.addEventListener("DOMContentLoaded", () => {
+                document.body.append(document.createElement("p"));
+                document.body.lastChild.textContent = window.injected;
+            });
+        "#;
+        let config = FetchConfig {
+            headless: true,
+            user_script: Some(script.to_string()),
+            ..Default::default()
+        };
+        let md = fetch_markdown("data:text/html,<p>Page content</p>", &config).await.unwrap();
+        assert!(md.contains("Page content"));
+        assert!(md.contains("Injected by user script"));
+    }
+
+    #[tokio::test]
+    #[ignore = "launches Chrome"]
+    async fn test_wait_for_selector() {
+        let page = concat!(
+            "data:text/html,<p>Loading</p><script>setTimeout(() => {",
+            "document.body.innerHTML = '<article>Rendered later</article>';",
+            "}, 1000)</script>",
+        );
+        let config = FetchConfig { headless: true, wait_for: Some("article".to_string()), ..Default::default() };
+        let md = fetch_markdown(page, &config).await.unwrap();
+        assert!(md.contains("Rendered later"));
+
+        let config = FetchConfig { wait_for: Some("#never".to_string()), ..config };
+        let err = fetch_markdown(page, &config).await.unwrap_err();
+        assert_eq!(err.exit_code(), 2);
+    }
+
+    #[tokio::test]
+    #[ignore = "launches Chrome"]
+    async fn test_delay_before_capture() {
+        let page = concat!(
+            "data:text/html,<p>Loading</p><script>setTimeout(() => {",
+            "document.body.innerHTML = '<p>Injected late</p>';",
+            "}, 500)</script>",
+        );
+        let config = FetchConfig { headless: true, ..Default::default() };
+        let md = fetch_markdown(page, &config).await.unwrap();
+        assert!(!md.contains("Injected late"));
+
+        let config = FetchConfig { delay_before_capture: Some(Duration::from_millis(1500)), ..config };
+        let md = fetch_markdown(page, &config).await.unwrap();
+        assert!(md.contains("Injected late"));
+    }
+
+    #[tokio::test]
+    #[ignore = "launches Chrome"]
+    async fn test_captures_preview_image() {
+        let page = concat!(
+            "data:text/html,<head>",
+            "<meta name=\"twitter:image\" content=\"https://example.com/card.png\">",
+            "<meta property=\"og:image\" content=\"https://example.com/hero.png\">",
+            "</head><p>Article</p>",
+        );
+        let config = FetchConfig { headless: true, ..Default::default() };
+        let session = Session::start(&config).await.unwrap();
+        let capture = capture_page(&session.browser, &config, page).await;
+        session.close().await.unwrap();
+        assert_eq!(capture.unwrap().image.as_deref(), Some("https://example.com/hero.png"));
+    }
+
+    #[tokio::test]
+    #[ignore = "launches Chrome"]
+    async fn test_detects_rendered_captcha_widgets() {
+        // Headless runs fail on a challenge instead of waiting for it
+        let config = FetchConfig { headless: true, ..Default::default() };
+        let widget = "data:text/html,<p>One more step</p><div class=g-recaptcha style='width:304px;height:78px'></div>";
+        let err = fetch_markdown(widget, &config).await.unwrap_err();
+        assert!(matches!(err, HeadfulError::ChallengeEncountered { kind: challenge::ChallengeKind::Captcha, .. }));
+
+        // Markup in a script or a hidden widget isn't a challenge
+        let quoted = "data:text/html,<p>One more step</p><script>const w = '<div class=\"g-recaptcha\"></div>';</script>";
+        assert!(fetch_markdown(quoted, &config).await.is_ok());
+        let hidden = "data:text/html,<p>One more step</p><div class=g-recaptcha style='display:none'></div>";
+        assert!(fetch_markdown(hidden, &config).await.is_ok());
+    }
+
+    #[tokio::test]
+    #[ignore = "launches Chrome"]
+    async fn test_follow_next_link() {
+        let first = "data:text/html,<a rel=next href='data:text/html,<p>Part two</p>'>Next</a><p>Part one</p>";
+        let config = FetchConfig {
+            headless: true,
+            follow_next: Some("rel=next".to_string()),
+            ..Default::default()
+        };
+        let md = fetch_markdown(first, &config).await.unwrap();
+        assert!(md.contains("Part one"));
+        assert!(md.contains("Part two"));
+
+        // A next page on a denied host isn't loaded, which would fail
+        let blocked = "data:text/html,<a rel=next href='https://blocked.invalid/2'>Next</a><p>Part one</p>";
+        assert!(fetch_markdown(blocked, &config).await.is_err());
+        let hosts = links::HostFilter { deny: vec!["blocked.invalid".to_string()], ..Default::default() };
+        let config = FetchConfig { host_filter: Some(hosts), ..config };
+        let md = fetch_markdown(blocked, &config).await.unwrap();
+        assert!(md.contains("Part one"));
+    }
+}
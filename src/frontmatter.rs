@@ -0,0 +1,92 @@
+use clap::ValueEnum;
+use serde::Serialize;
+
+/// Serialization used for the frontmatter block
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum FrontmatterFormat {
+    /// YAML between `---` delimiters
+    #[default]
+    Yaml,
+    /// TOML between `+++` delimiters
+    Toml,
+}
+
+/// Metadata about the converted page
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Metadata {
+    /// The URL that was fetched
+    pub url: String,
+    /// The page title, if it has one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// The page's preview image from `og:image` or `twitter:image`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// Estimated reading time in minutes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reading_time: Option<usize>,
+}
+
+/// Render metadata as a frontmatter block to prepend to the markdown
+pub fn render(metadata: &Metadata, format: FrontmatterFormat) -> Result<String, Box<dyn std::error::Error>> {
+    match format {
+        FrontmatterFormat::Yaml => {
+            // JSON scalars are valid YAML so serde_json handles quoting
+            // and escaping of each value
+            let mut block = String::from("---\n");
+            if let serde_json::Value::Object(fields) = serde_json::to_value(metadata)? {
+                for (key, value) in fields {
+                    block.push_str(&format!("{}: {}\n", key, value));
+                }
+            }
+            block.push_str("---\n");
+            Ok(block)
+        }
+        FrontmatterFormat::Toml => {
+            Ok(format!("+++\n{}+++\n", toml::to_string(metadata)?))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata() -> Metadata {
+        Metadata {
+            url: "https://example.com/".to_string(),
+            title: Some("Example \"Domain\"".to_string()),
+            image: None,
+            reading_time: None,
+        }
+    }
+
+    #[test]
+    fn test_yaml_frontmatter() {
+        let block = render(&metadata(), FrontmatterFormat::Yaml).unwrap();
+        assert_eq!(block, "---\nurl: \"https://example.com/\"\ntitle: \"Example \\\"Domain\\\"\"\n---\n");
+    }
+
+    #[test]
+    fn test_reading_time_frontmatter() {
+        let metadata = Metadata { reading_time: Some(4), ..metadata() };
+        let block = render(&metadata, FrontmatterFormat::Yaml).unwrap();
+        assert!(block.ends_with("reading_time: 4\n---\n"));
+    }
+
+    #[test]
+    fn test_image_frontmatter() {
+        let metadata = Metadata { image: Some("https://example.com/hero.png".to_string()), ..metadata() };
+        let block = render(&metadata, FrontmatterFormat::Yaml).unwrap();
+        assert!(block.ends_with("image: \"https://example.com/hero.png\"\n---\n"));
+    }
+
+    #[test]
+    fn test_toml_frontmatter() {
+        let block = render(&metadata(), FrontmatterFormat::Toml).unwrap();
+        assert!(block.starts_with("+++\n"));
+        assert!(block.ends_with("+++\n"));
+        assert!(block.contains("url = \"https://example.com/\"\n"));
+        assert!(block.contains("title = 'Example \"Domain\"'\n"));
+    }
+}
@@ -0,0 +1,70 @@
+use std::collections::BTreeMap;
+
+/// Headers that can carry credentials or session state
+const SENSITIVE_HEADERS: [&str; 5] = ["set-cookie", "cookie", "authorization", "proxy-authorization", "www-authenticate"];
+
+/// Placeholder shown in place of a sensitive header's value
+const REDACTED: &str = "[redacted]";
+
+/// Collect response headers from CDP's header object, lowercasing names
+/// and redacting sensitive values unless asked to include them
+pub fn collect(headers: &serde_json::Value, include_sensitive: bool) -> BTreeMap<String, String> {
+    let Some(headers) = headers.as_object() else {
+        return BTreeMap::new();
+    };
+    headers.iter()
+        .map(|(name, value)| {
+            let name = name.to_ascii_lowercase();
+            let value = if !include_sensitive && SENSITIVE_HEADERS.contains(&name.as_str()) {
+                REDACTED.to_string()
+            } else {
+                match value {
+                    serde_json::Value::String(s) => s.clone(),
+                    value => value.to_string(),
+                }
+            };
+            (name, value)
+        })
+        .collect()
+}
+
+/// Render headers as an HTML comment so they ride along with the
+/// markdown without showing up when it's rendered
+pub fn comment_block(headers: &BTreeMap<String, String>) -> String {
+    let mut block = String::from("<!--\n");
+    for (name, value) in headers {
+        // Repeated headers are joined with newlines by Chrome
+        for line in value.lines() {
+            block.push_str(&format!("{}: {}\n", name, line.replace("--", "- -")));
+        }
+    }
+    block.push_str("-->\n");
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dump_headers() {
+        let raw = serde_json::json!({
+            "Content-Type": "text/html; charset=utf-8",
+            "cache-control": "max-age=600",
+            "set-cookie": "session=abc123\ntheme=dark",
+        });
+
+        let headers = collect(&raw, false);
+        assert_eq!(headers["content-type"], "text/html; charset=utf-8");
+        assert_eq!(headers["set-cookie"], "[redacted]");
+
+        let block = comment_block(&headers);
+        assert_eq!(
+            block,
+            "<!--\ncache-control: max-age=600\ncontent-type: text/html; charset=utf-8\nset-cookie: [redacted]\n-->\n"
+        );
+
+        let headers = collect(&raw, true);
+        assert!(comment_block(&headers).contains("set-cookie: session=abc123\nset-cookie: theme=dark\n"));
+    }
+}
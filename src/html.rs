@@ -0,0 +1,201 @@
+use htmd::element_handler::Handlers;
+use htmd::{Element, HtmlToMarkdown};
+
+use crate::style::{self, EmphasisStyle, HrStyle};
+
+/// HTML elements dropped before conversion by default
+pub const SKIP_TAGS: [&str; 8] = ["script", "style", "footer", "img", "svg", "iframe", "head", "link"];
+
+/// Resolve the elements to drop before conversion. `skip` replaces the
+/// default set when given, then the tags in `keep` are taken out of it.
+pub fn skip_tags(skip: &[String], keep: &[String]) -> Vec<String> {
+    let normalize = |tag: &String| tag.trim().to_ascii_lowercase();
+    let tags: Vec<String> = if skip.is_empty() {
+        SKIP_TAGS.iter().map(|tag| tag.to_string()).collect()
+    } else {
+        skip.iter().map(normalize).filter(|tag| !tag.is_empty()).collect()
+    };
+    let keep: Vec<String> = keep.iter().map(normalize).collect();
+    tags.into_iter().filter(|tag| !keep.contains(tag)).collect()
+}
+
+/// Build the HTML to markdown converter, dropping the given elements
+pub fn converter(emphasis: EmphasisStyle, hr: HrStyle, skip_tags: &[String]) -> HtmlToMarkdown {
+    HtmlToMarkdown::builder()
+        .add_handler(vec!["hr"], move |_: &dyn Handlers, _: Element| {
+            Some(format!("\n\n{}\n\n", hr.rule()).into())
+        })
+        .add_handler(vec!["i", "em"], move |handlers: &dyn Handlers, element: Element| {
+            emphasis_handler(handlers, element, emphasis.italic())
+        })
+        .add_handler(vec!["strong", "b"], move |handlers: &dyn Handlers, element: Element| {
+            emphasis_handler(handlers, element, emphasis.bold())
+        })
+        // Added last so skipping wins over the handlers above
+        .skip_tags(skip_tags.iter().map(String::as_str).collect())
+        .build()
+}
+
+/// Render an emphasis element's children wrapped in the given marker
+fn emphasis_handler(
+    handlers: &dyn Handlers,
+    element: Element,
+    marker: &str,
+) -> Option<htmd::element_handler::HandlerResult> {
+    let content = handlers.walk_children(element.node).content;
+    if content.trim().is_empty() {
+        return None;
+    }
+    Some(style::wrap_emphasis(&content, marker).into())
+}
+
+/// Convert HTML to markdown, falling back to the plain text content of
+/// the page when htmd fails so one bad page doesn't abort the run
+pub fn convert(converter: &HtmlToMarkdown, html: &str) -> String {
+    markdown_or_fallback(converter.convert(html), html)
+}
+
+/// Re-encode `&` in the text between tags so entities survive parsing
+/// and appear literally in the converted markdown. Attribute values are
+/// left alone so URLs still decode normally.
+pub fn preserve_entities(html: &str) -> String {
+    let mut preserved = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            '&' if !in_tag => {
+                preserved.push_str("&amp;");
+                continue;
+            }
+            _ => {}
+        }
+        preserved.push(c);
+    }
+    preserved
+}
+
+/// Encode ampersands and angle brackets as HTML entities
+pub fn encode_entities(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => encoded.push_str("&amp;"),
+            '<' => encoded.push_str("&lt;"),
+            '>' => encoded.push_str("&gt;"),
+            _ => encoded.push(c),
+        }
+    }
+    encoded
+}
+
+/// Use the converted markdown or warn and fall back to stripped text
+fn markdown_or_fallback(result: std::io::Result<String>, html: &str) -> String {
+    match result {
+        Ok(markdown) => markdown,
+        Err(e) => {
+            eprintln!("Warning: HTML conversion failed ({}), falling back to plain text", e);
+            strip_tags(html)
+        }
+    }
+}
+
+/// Naively strip tags from HTML, dropping script and style contents and
+/// collapsing whitespace within each line
+fn strip_tags(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        let after = &rest[start..];
+        let Some(end) = after.find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = after[1..end].trim().to_ascii_lowercase();
+        rest = &after[end + 1..];
+
+        // Skip over the body of elements that never contain prose
+        for skipped in ["script", "style"] {
+            if tag == skipped || tag.starts_with(&format!("{} ", skipped)) {
+                let close = format!("</{}", skipped);
+                rest = match rest.to_ascii_lowercase().find(&close) {
+                    Some(pos) => &rest[pos..],
+                    None => "",
+                };
+            }
+        }
+    }
+    text.push_str(rest);
+
+    let lines: Vec<String> = text
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<&str>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect();
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_html() {
+        let md = convert(&converter(EmphasisStyle::default(), HrStyle::default(), &skip_tags(&[], &[])), "<h1>Title</h1><script>var x;</script><p>Body</p>");
+        assert_eq!(md, "# Title\n\nBody");
+    }
+
+    #[test]
+    fn test_emphasis_style() {
+        let html = "<p>An <em>italic</em> and <strong>bold</strong> word</p>";
+        let md = convert(&converter(EmphasisStyle::Asterisk, HrStyle::default(), &skip_tags(&[], &[])), html);
+        assert_eq!(md, "An *italic* and **bold** word");
+        let md = convert(&converter(EmphasisStyle::Underscore, HrStyle::default(), &skip_tags(&[], &[])), html);
+        assert_eq!(md, "An _italic_ and __bold__ word");
+    }
+
+    #[test]
+    fn test_hr_style() {
+        let html = "<p>Above</p><hr><p>Below</p>";
+        let md = convert(&converter(EmphasisStyle::default(), HrStyle::Asterisks, &skip_tags(&[], &[])), html);
+        assert_eq!(md, "Above\n\n***\n\nBelow");
+    }
+
+    #[test]
+    fn test_skip_tags() {
+        let html = "<p>Logo <img src=\"/logo.png\" alt=\"Example\"></p><footer>Contact us</footer>";
+        let md = convert(&converter(EmphasisStyle::default(), HrStyle::default(), &skip_tags(&[], &[])), html);
+        assert_eq!(md, "Logo");
+
+        // Keeping tags takes them out of the default set
+        let keep = vec!["img".to_string(), "FOOTER".to_string()];
+        let md = convert(&converter(EmphasisStyle::default(), HrStyle::default(), &skip_tags(&[], &keep)), html);
+        assert_eq!(md, "Logo ![Example](/logo.png)\n\nContact us");
+
+        // Skipping tags replaces the default set
+        let skip = vec!["footer".to_string()];
+        assert_eq!(skip_tags(&skip, &[]), vec!["footer"]);
+        let md = convert(&converter(EmphasisStyle::default(), HrStyle::default(), &skip_tags(&skip, &[])), html);
+        assert_eq!(md, "Logo ![Example](/logo.png)");
+    }
+
+    #[test]
+    fn test_preserve_entities() {
+        let html = "<p>Fish &amp; chips &lt;3 <a href=\"/menu?a=1&amp;b=2\">menu</a></p>";
+        let md = convert(&converter(EmphasisStyle::default(), HrStyle::default(), &skip_tags(&[], &[])), &preserve_entities(html));
+        assert_eq!(md, "Fish &amp; chips &lt;3 [menu](/menu?a=1&b=2)");
+
+        let md = convert(&converter(EmphasisStyle::default(), HrStyle::default(), &skip_tags(&[], &[])), html);
+        assert_eq!(md, "Fish & chips <3 [menu](/menu?a=1&b=2)");
+    }
+
+    #[test]
+    fn test_conversion_failure_falls_back_to_text() {
+        let html = "<html><head><style>p { color: red; }</style></head>\n<body><p>Hello <b>world</b></p>\n<script>alert(1)</script><p>Bye</p></body></html>";
+        let err = std::io::Error::other("pathological html");
+        let md = markdown_or_fallback(Err(err), html);
+        assert_eq!(md, "Hello world\nBye");
+    }
+}
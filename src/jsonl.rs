@@ -0,0 +1,72 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+/// One converted page in JSON-lines output
+#[derive(Debug, Serialize)]
+pub struct Record<'a> {
+    pub url: &'a str,
+    pub markdown: &'a str,
+    pub title: Option<&'a str>,
+    /// The page's preview image, from `og:image` or `twitter:image`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<&'a str>,
+    /// HTTP status of the navigation response, if one was seen
+    pub status: Option<i64>,
+    /// Estimated reading time in minutes, with `--reading-time`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reading_time: Option<usize>,
+    /// Response headers, with `--dump-headers`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub headers: Option<&'a BTreeMap<String, String>>,
+}
+
+/// Write a record as a single line and flush it so consumers see each
+/// page as soon as it completes. Newlines in the markdown are escaped by
+/// serde so every record stays on one line.
+pub fn write_record<W: Write>(sink: &mut W, record: &Record) -> io::Result<()> {
+    serde_json::to_writer(&mut *sink, record)?;
+    sink.write_all(b"\n")?;
+    sink.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_one_record_per_line() {
+        let mut out = Vec::new();
+        write_record(&mut out, &Record {
+            url: "https://example.com/a",
+            markdown: "# A\n\nFirst page",
+            title: Some("A"),
+            image: None,
+            status: Some(200),
+            reading_time: None,
+            headers: None,
+        }).unwrap();
+        write_record(&mut out, &Record {
+            url: "https://example.com/b",
+            markdown: "# B\n\nSecond page",
+            title: None,
+            image: None,
+            status: Some(404),
+            reading_time: Some(1),
+            headers: None,
+        }).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            r##"{"url":"https://example.com/a","markdown":"# A\n\nFirst page","title":"A","status":200}"##
+        );
+        let second: serde_json::Value = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(second["markdown"], "# B\n\nSecond page");
+        assert_eq!(second["status"], 404);
+        assert_eq!(second["reading_time"], 1);
+    }
+}
@@ -0,0 +1,109 @@
+use serde_json::Value;
+
+/// Collect the JSON-LD blocks embedded in a page's
+/// `<script type="application/ld+json">` tags. Blocks that fail to parse
+/// are skipped, and top-level arrays and `@graph` lists are flattened so
+/// each item can be queried on its own.
+pub fn blocks(html: &str) -> Vec<Value> {
+    let lower = html.to_ascii_lowercase();
+    let mut blocks = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = lower[offset..].find("<script") {
+        let tag_start = offset + start;
+        let Some(tag_len) = lower[tag_start..].find('>') else {
+            break;
+        };
+        let body_start = tag_start + tag_len + 1;
+        let Some(body_len) = lower[body_start..].find("</script") else {
+            break;
+        };
+        let body_end = body_start + body_len;
+        offset = body_end;
+
+        if !lower[tag_start..body_start].contains("application/ld+json") {
+            continue;
+        }
+        match serde_json::from_str::<Value>(html[body_start..body_end].trim()) {
+            Ok(value) => flatten(value, &mut blocks),
+            Err(e) => eprintln!("Skipping invalid JSON-LD block: {}", e),
+        }
+    }
+    blocks
+}
+
+/// Push a JSON-LD value, expanding arrays and `@graph` lists
+fn flatten(value: Value, blocks: &mut Vec<Value>) {
+    match value {
+        Value::Array(items) => {
+            for item in items {
+                flatten(item, blocks);
+            }
+        }
+        Value::Object(mut object) if object.contains_key("@graph") => {
+            if let Some(graph) = object.remove("@graph") {
+                flatten(graph, blocks);
+            }
+            // Keep any other fields that sit alongside the graph
+            if object.keys().any(|key| key != "@context") {
+                blocks.push(Value::Object(object));
+            }
+        }
+        value => blocks.push(value),
+    }
+}
+
+/// Look up a JSON pointer such as `/offers/price` in every block,
+/// returning the values found. Blocks without the field are skipped.
+pub fn extract<'a>(blocks: &'a [Value], pointer: &str) -> Vec<&'a Value> {
+    blocks.iter().filter_map(|block| block.pointer(pointer)).collect()
+}
+
+/// Format an extracted value for output, printing strings without quotes
+pub fn display(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"<html><head>
+        <script type="application/ld+json">
+            {"@context": "https://schema.org", "@type": "Organization", "name": "Acme"}
+        </script>
+        <script>var notJsonLd = {"offers": {"price": "0"}};</script>
+        <SCRIPT type='application/ld+json'>
+            {
+                "@context": "https://schema.org",
+                "@type": "Product",
+                "name": "Anvil",
+                "offers": {"@type": "Offer", "price": "19.99", "priceCurrency": "USD"}
+            }
+        </SCRIPT>
+        <script type="application/ld+json">{ not json }</script>
+        </head><body></body></html>"#;
+
+    #[test]
+    fn test_extract_offer_price() {
+        let blocks = blocks(PAGE);
+        assert_eq!(blocks.len(), 2);
+
+        let prices = extract(&blocks, "/offers/price");
+        assert_eq!(prices.len(), 1);
+        assert_eq!(display(prices[0]), "19.99");
+
+        assert_eq!(extract(&blocks, "/name").iter().map(|v| display(v)).collect::<Vec<_>>(), vec!["Acme", "Anvil"]);
+        assert!(extract(&blocks, "/missing/field").is_empty());
+    }
+
+    #[test]
+    fn test_graph_is_flattened() {
+        let html = r#"<script type="application/ld+json">{"@context": "https://schema.org", "@graph": [{"@type": "WebPage"}, {"@type": "Product", "sku": "A1"}]}</script>"#;
+        let blocks = blocks(html);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(display(extract(&blocks, "/sku")[0]), "A1");
+    }
+}
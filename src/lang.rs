@@ -0,0 +1,68 @@
+use chromiumoxide::cdp::browser_protocol::network::{
+    Headers, SetExtraHttpHeadersParams, SetUserAgentOverrideParams,
+};
+
+/// Loosely validate a BCP-47 language tag like `fr` or `fr-FR`: a 2-3
+/// letter primary language followed by alphanumeric subtags of 1-8
+/// characters
+pub fn parse_lang(tag: &str) -> Result<String, String> {
+    let mut subtags = tag.split('-');
+    let primary = subtags.next().unwrap_or_default();
+    let valid = (2..=3).contains(&primary.len())
+        && primary.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|s| (1..=8).contains(&s.len()) && s.chars().all(|c| c.is_ascii_alphanumeric()));
+    if valid {
+        Ok(tag.to_string())
+    } else {
+        Err(format!("`{}` is not a language tag like `en` or `fr-FR`", tag))
+    }
+}
+
+/// The `Accept-Language` value for a tag, falling back to the primary
+/// language so `fr-FR` still matches pages only offered in `fr`
+pub fn accept_language(tag: &str) -> String {
+    match tag.split_once('-') {
+        Some((primary, _)) => format!("{},{};q=0.9", tag, primary),
+        None => tag.to_string(),
+    }
+}
+
+/// CDP parameters sending the `Accept-Language` header on every request
+pub fn header_params(tag: &str) -> SetExtraHttpHeadersParams {
+    SetExtraHttpHeadersParams::new(Headers::new(serde_json::json!({
+        "Accept-Language": accept_language(tag),
+    })))
+}
+
+/// CDP parameters emulating `navigator.language`, keeping the given
+/// user agent
+pub fn emulation_params(tag: &str, user_agent: &str) -> SetUserAgentOverrideParams {
+    let mut params = SetUserAgentOverrideParams::new(user_agent);
+    params.accept_language = Some(accept_language(tag));
+    params
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lang() {
+        assert_eq!(parse_lang("fr-FR"), Ok("fr-FR".to_string()));
+        assert!(parse_lang("en").is_ok());
+        assert!(parse_lang("zh-Hant-TW").is_ok());
+        assert!(parse_lang("french").is_err());
+        assert!(parse_lang("fr_FR").is_err());
+        assert!(parse_lang("").is_err());
+    }
+
+    #[test]
+    fn test_fr_fr_params() {
+        let headers = header_params("fr-FR");
+        assert_eq!(headers.headers.inner()["Accept-Language"], "fr-FR,fr;q=0.9");
+
+        let emulation = emulation_params("fr-FR", "Mozilla/5.0");
+        assert_eq!(emulation.user_agent, "Mozilla/5.0");
+        assert_eq!(emulation.accept_language.as_deref(), Some("fr-FR,fr;q=0.9"));
+    }
+}
@@ -0,0 +1,38 @@
+//! Convert web pages to markdown by loading them in a headful Chrome
+//! browser.
+//!
+//! [`fetch_markdown`] launches a browser, loads a URL and converts it in
+//! one call. The building blocks it uses ([`fetch::Session`],
+//! [`fetch::capture_page`] and [`fetch::convert_capture`]) are available
+//! for converting several pages with one browser.
+
+pub mod axtree;
+pub mod browser;
+pub mod challenge;
+pub mod deadline;
+pub mod device;
+pub mod diff;
+pub mod dot;
+pub mod error;
+pub mod fetch;
+pub mod frontmatter;
+pub mod headers;
+pub mod html;
+pub mod jsonl;
+pub mod jsonld;
+pub mod lang;
+pub mod links;
+pub mod metrics;
+pub mod output;
+pub mod pagination;
+pub mod repl;
+pub mod sink;
+pub mod slug;
+pub mod stats;
+pub mod style;
+pub mod template;
+#[cfg(test)]
+mod test_support;
+
+pub use error::HeadfulError;
+pub use fetch::{FetchConfig, fetch_markdown};
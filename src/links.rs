@@ -0,0 +1,121 @@
+use url::Url;
+
+/// Normalize a URL so equivalent forms compare equal.
+///
+/// Lowercases the scheme and host, strips default ports and resolves
+/// `.`/`..` path segments. URLs that fail to parse (e.g. relative
+/// links) are returned unchanged.
+pub fn normalize_url(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(parsed) => parsed.to_string(),
+        Err(_) => url.to_string(),
+    }
+}
+
+/// Get the lowercased host of a URL
+pub fn host_of(url: &str) -> Option<String> {
+    Url::parse(url).ok()?.host_str().map(|h| h.to_ascii_lowercase())
+}
+
+/// Scope control for which hosts may be fetched.
+///
+/// Patterns match a host exactly or, with a leading `*.`, any subdomain
+/// of it (`*.example.com` matches `blog.example.com` but not
+/// `example.com`). Denied hosts always lose; when any allow patterns are
+/// given, a host must match one of them or one of the seed URLs' hosts.
+/// URLs without a host (`data:`, `file:`) only fail an allow list.
+#[derive(Debug, Clone, Default)]
+pub struct HostFilter {
+    pub seed_hosts: Vec<String>,
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+}
+
+impl HostFilter {
+    /// Check if a URL may be fetched, returning the reason when it can't
+    pub fn check(&self, url: &str) -> Result<(), String> {
+        if self.allow.is_empty() && self.deny.is_empty() {
+            return Ok(());
+        }
+        let parsed = Url::parse(url).map_err(|e| format!("invalid URL {}: {}", url, e))?;
+        let Some(host) = parsed.host_str().map(|h| h.to_ascii_lowercase()) else {
+            return match self.allow.is_empty() {
+                true => Ok(()),
+                false => Err(format!("URL {} has no host to match --allow-host", url)),
+            };
+        };
+
+        if let Some(pattern) = self.deny.iter().find(|p| host_matches(p, &host)) {
+            return Err(format!("host {} is denied by --deny-host {}", host, pattern));
+        }
+        if self.allow.is_empty()
+            || self.seed_hosts.contains(&host)
+            || self.allow.iter().any(|p| host_matches(p, &host))
+        {
+            Ok(())
+        } else {
+            Err(format!("host {} is not in --allow-host", host))
+        }
+    }
+}
+
+/// Match a host against a pattern, supporting a leading `*.` wildcard
+fn host_matches(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host.ends_with(&format!(".{}", domain)),
+        None => host == pattern,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_equivalent_urls() {
+        let forms = [
+            "HTTP://Example.com:80/",
+            "http://example.com/",
+            "http://EXAMPLE.com",
+            "http://example.com/a/../",
+            "http://example.com/./",
+        ];
+        for form in forms {
+            assert_eq!(normalize_url(form), "http://example.com/", "{}", form);
+        }
+        assert_eq!(normalize_url("https://example.com:443/x"), "https://example.com/x");
+        assert_eq!(normalize_url("https://example.com:8443/x"), "https://example.com:8443/x");
+    }
+
+    #[test]
+    fn test_normalize_relative_url_unchanged() {
+        assert_eq!(normalize_url("/about"), "/about");
+    }
+
+    #[test]
+    fn test_host_filter() {
+        let filter = HostFilter {
+            seed_hosts: vec!["example.com".to_string(), "docs.rs".to_string()],
+            allow: vec!["*.example.com".to_string()],
+            deny: vec!["ads.example.com".to_string(), "tracker.net".to_string()],
+        };
+        assert!(filter.check("https://example.com/").is_ok());
+        assert!(filter.check("https://blog.example.com/post").is_ok());
+        assert!(filter.check("https://ads.example.com/").unwrap_err().contains("--deny-host ads.example.com"));
+        assert!(filter.check("https://tracker.net/").unwrap_err().contains("denied"));
+        assert!(filter.check("https://other.org/").unwrap_err().contains("not in --allow-host"));
+        assert!(filter.check("https://docs.rs/url").is_ok());
+        assert!(filter.check("data:text/html,<p>Hi</p>").unwrap_err().contains("no host"));
+    }
+
+    #[test]
+    fn test_host_filter_passes_hostless_urls() {
+        let unset = HostFilter::default();
+        assert!(unset.check("data:text/html,<p>Hi</p>").is_ok());
+        assert!(unset.check("file:///tmp/page.html").is_ok());
+        let deny = HostFilter { deny: vec!["tracker.net".to_string()], ..Default::default() };
+        assert!(deny.check("file:///tmp/page.html").is_ok());
+        assert!(deny.check("https://tracker.net/").is_err());
+    }
+}
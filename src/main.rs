@@ -1,43 +1,17 @@
 use std::io;
-use std::io::Write;
-use futures_util::StreamExt;
-use serde::{Serialize, Deserialize};
-use serde_json::Value;
-use tokio::task;
-use htmd::HtmlToMarkdown;
-use chromiumoxide::{Command, Method, browser::{Browser, BrowserConfig}};
+use std::time::{Duration, Instant};
 use clap::Parser;
-
-mod axtree;
-
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub struct GetFullAxTree;
-
-impl Command for GetFullAxTree {
-    type Response = Value;
-}
-
-impl Method for GetFullAxTree {
-    fn identifier(&self) -> chromiumoxide::types::MethodId {
-        chromiumoxide::types::MethodId::Borrowed("Accessibility.getFullAXTree")
-    }
-}
-
+use headful::fetch::{self, FetchConfig};
+use headful::{
+    HeadfulError, axtree, browser, deadline, device, diff, error, frontmatter, headers, html, jsonl, jsonld,
+    lang, links, metrics, output, repl, sink, slug, stats, style, template,
+};
 
 #[cfg(feature = "llm")]
 use reqwest::Client;
 #[cfg(feature = "llm")]
 use serde::{Deserialize, Serialize};
 
-
-fn wait_for_enter(prompt: &str) -> io::Result<()> {
-    print!("{prompt}");
-    io::stdout().flush()?;          // Propagate any flushing error
-    let mut line = String::new();
-    io::stdin().read_line(&mut line)?; // Propagate read errors
-    Ok(())
-}
-
 #[cfg(feature = "llm")]
 #[derive(Serialize)]
 struct OpenAIRequest {
@@ -115,19 +89,430 @@ async fn cleanup_with_llm(
         .clone())
 }
 
+/// Output format for the converted page
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum OutputFormat {
+    /// Markdown text
+    Markdown,
+    /// JSON tree of structural blocks (uses the accessibility tree)
+    Ast,
+    /// One JSON object per page with the url, markdown, title and HTTP
+    /// status, written as each page completes
+    Jsonl,
+    /// The page's raw accessibility tree as JSON, for debugging conversions
+    Json,
+    /// The page's accessibility tree as a GraphViz DOT graph
+    Dot,
+}
+
+impl OutputFormat {
+    /// Whether the output is data for another program, which URL
+    /// headings, rules and `--prepend`/`--append` text would break
+    fn is_data(self) -> bool {
+        matches!(self, OutputFormat::Ast | OutputFormat::Json | OutputFormat::Dot)
+    }
+}
+
 /// Convert HTML web pages to Markdown format using a headful Chrome browser.
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 #[command(arg_required_else_help = true)]
 struct Cli {
-    /// The URL to fetch and convert to Markdown
-    url: String,
+    /// The URLs to fetch and convert to Markdown. Multiple pages are
+    /// printed one after another, each under its URL as a heading
+    #[arg(required = true, value_name = "URL")]
+    urls: Vec<String>,
 
     /// Experimental: Use accessibility tree instead of HTML for
     /// markdown conversion
+    ///
+    /// By default the page's HTML is converted, which keeps its full
+    /// markup but also menus, cookie banners and layout wrappers. The
+    /// accessibility tree describes the page the way a screen reader sees
+    /// it (headings, links, lists, tables, landmarks), which usually gives
+    /// cleaner output for app-like pages. Flags marked "axtree mode" only
+    /// apply here.
     #[arg(short, long)]
     axtree: bool,
 
+    /// Normalize link URLs by lowercasing the scheme and host, removing
+    /// default ports and resolving dot segments (axtree mode)
+    #[arg(long)]
+    normalize_links: bool,
+
+    /// Embed images as base64 data URIs for self-contained output
+    /// (axtree mode)
+    #[arg(long)]
+    inline_images: bool,
+
+    /// Skip inlining images larger than this many bytes
+    #[arg(long, default_value_t = 1_000_000)]
+    max_image_bytes: usize,
+
+    /// Number of columns in the page layout. Content is reordered so each
+    /// column is emitted in full before the next (axtree mode)
+    #[arg(long)]
+    columns: Option<usize>,
+
+    /// Render footer content without the `--- Footer ---` label
+    /// (axtree mode)
+    #[arg(long)]
+    no_footer_marker: bool,
+
+    /// Override the role name for one of Chrome's internal role values,
+    /// e.g. `158=StaticText`. Can be repeated (axtree mode)
+    #[arg(long, value_name = "VALUE=NAME", value_parser = parse_internal_role)]
+    internal_role: Vec<(i64, String)>,
+
+    /// Emit the page text as a single paragraph without any markdown
+    /// structure (axtree mode)
+    #[arg(long)]
+    flatten: bool,
+
+    /// Rewrite superscript links to same-page notes as markdown
+    /// footnotes with definitions at the end (axtree mode)
+    #[arg(long)]
+    footnotes: bool,
+
+    /// Render links inside navigation and banner landmarks as plain text
+    /// (axtree mode)
+    #[arg(long)]
+    strip_nav_links: bool,
+
+    /// Follow each emitted block with a `<!-- role -->` comment naming
+    /// the accessibility role that produced it (axtree mode)
+    #[arg(long)]
+    debug_roles: bool,
+
+    /// Backslash-escape markdown characters such as `#`, `*` and `_` in
+    /// text so they render literally. Code is left as is (axtree mode)
+    #[arg(long)]
+    escape_markdown: bool,
+
+    /// Keep content hidden with `aria-hidden`, such as off-screen menus,
+    /// which is skipped by default (axtree mode)
+    #[arg(long)]
+    include_aria_hidden: bool,
+
+    /// Render note, aside and alert regions as `> [!NOTE]` style callouts
+    /// (axtree mode)
+    #[arg(long)]
+    callouts: bool,
+
+    /// Start the document with a table of contents linking to each
+    /// heading (axtree mode)
+    #[arg(long)]
+    toc: bool,
+
+    /// Only list headings up to this level in the table of contents,
+    /// implies `--toc` (axtree mode)
+    #[arg(long, value_name = "N")]
+    toc_depth: Option<i64>,
+
+    /// Anchor style for table of contents links, matching the renderer
+    /// the markdown is for (axtree mode)
+    #[arg(long, value_enum, default_value_t = slug::SlugStyle::Github)]
+    slug_style: slug::SlugStyle,
+
+    /// Capture the page again after this many seconds and only convert
+    /// content added in between, such as new feed items (axtree mode)
+    #[arg(long, value_name = "SECONDS")]
+    new_after: Option<u64>,
+
+    /// Remove elements matching this CSS selector, and everything inside
+    /// them, before converting. Can be repeated (axtree mode)
+    #[arg(long, value_name = "CSS")]
+    selector_exclude: Vec<String>,
+
+    /// Render multi-line code as a fenced block line by line, keeping
+    /// indentation that would otherwise be collapsed (axtree mode)
+    #[arg(long)]
+    prettify_whitespace: bool,
+
+    /// Skip paragraphs and list items whose text is shorter than this
+    /// many characters. Links and headings are kept (axtree mode)
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    min_text_length: usize,
+
+    /// Stop converting at the last complete heading, paragraph or list
+    /// item that fits in this many bytes (axtree mode)
+    #[arg(long, value_name = "N")]
+    max_bytes: Option<usize>,
+
+    /// Drop a link to the same URL as the link right before it, such as a
+    /// thumbnail and title for one article, keeping the one with more
+    /// text (axtree mode)
+    #[arg(long)]
+    dedupe_adjacent_links: bool,
+
+    /// Render a heading directly followed by a lower-level heading, such
+    /// as an article's title and subtitle, as the title with the subtitle
+    /// in italics beneath it (axtree mode)
+    #[arg(long)]
+    merge_subtitles: bool,
+
+    /// Stop descending into the tree below this many levels of nesting,
+    /// noting at the end when content was cut off. Defaults to 256 (axtree mode)
+    #[arg(long, value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// Keep HTML entities such as `&amp;` literally in the output instead
+    /// of decoding them
+    #[arg(long)]
+    raw_entities: bool,
+
+    /// Write markdown to stdout as it is converted instead of buffering
+    /// the whole document, to bound memory on huge pages (axtree mode)
+    #[arg(long)]
+    stream: bool,
+
+    /// Fail when the accessibility tree references child nodes that
+    /// don't exist, printing the offending parent and child IDs
+    #[arg(long)]
+    strict_tree: bool,
+
+    /// Fail when the accessibility tree has nothing to convert, telling a
+    /// capture with no nodes apart from a page whose nodes are all
+    /// ignored (axtree mode)
+    #[arg(long)]
+    fail_on_empty: bool,
+
+    /// Send this User-Agent string instead of Chromium's default. Takes
+    /// precedence over `--device`
+    #[arg(long, value_name = "STRING")]
+    user_agent: Option<String>,
+
+    /// Request content in this language, e.g. `fr-FR`, by setting the
+    /// Accept-Language header and navigator.language
+    #[arg(long, value_name = "CODE", value_parser = lang::parse_lang)]
+    lang: Option<String>,
+
+    /// Run the JavaScript in this file at the start of every document,
+    /// including subframes, before the page's own scripts. Useful for
+    /// stubbing analytics or patching APIs
+    #[arg(long, value_name = "FILE", value_parser = read_file)]
+    user_script: Option<String>,
+
+    /// Wait for an element matching this CSS selector before capturing
+    /// the page, for apps that render their content after loading. Fails
+    /// if it hasn't appeared after 30 seconds
+    #[arg(long, value_name = "SELECTOR")]
+    wait_for: Option<String>,
+
+    /// Wait this many milliseconds after the page loads (and after
+    /// `--wait-for`) before capturing it, so late scripts can settle
+    #[arg(long, value_name = "MS")]
+    delay_before_capture: Option<u64>,
+
+    /// Path to the Chrome or Chromium binary to launch, defaulting to the
+    /// CHROME environment variable
+    #[arg(long, value_name = "PATH", env = "CHROME", value_parser = browser::parse_executable)]
+    chrome_path: Option<std::path::PathBuf>,
+
+    /// Fail with an error instead of prompting when a CAPTCHA or browser
+    /// check is detected. Always the case when stdin isn't a terminal
+    #[arg(long, visible_alias = "non-interactive")]
+    fail_on_challenge: bool,
+
+    /// Wait up to this many seconds for a CAPTCHA or browser check to be
+    /// cleared in the browser window, re-checking the page instead of
+    /// waiting for return to be pressed
+    #[arg(long, value_name = "SECONDS")]
+    captcha_timeout: Option<u64>,
+
+    /// How often to re-check the page while waiting for a challenge to
+    /// clear, in milliseconds
+    #[arg(long, value_name = "MS", default_value_t = 1000, requires = "captcha_timeout")]
+    captcha_poll_interval: u64,
+
+    /// Run Chrome without a visible window, for servers and CI. CAPTCHAs
+    /// and browser checks fail instead of prompting
+    #[arg(long, conflicts_with = "connect")]
+    headless: bool,
+
+    /// Connect to an already running Chrome DevTools endpoint (a `ws://`
+    /// URL or `http://host:port`) instead of launching a new browser
+    #[arg(long, value_name = "ENDPOINT")]
+    connect: Option<String>,
+
+    /// Route the browser's traffic through a proxy server, e.g.
+    /// `http://proxy:3128` or `socks5://127.0.0.1:1080`
+    #[arg(long, value_name = "URL", value_parser = browser::parse_proxy, conflicts_with = "connect")]
+    proxy: Option<String>,
+
+    /// Emulate a device's viewport, scale factor, user agent and touch
+    /// support (iphone-14, pixel-7, ipad, desktop)
+    #[arg(long, value_parser = device::parse_preset)]
+    device: Option<&'static device::DevicePreset>,
+
+    /// Width of the emulated window in CSS pixels, taking precedence over
+    /// `--device` [default: 1280]. Narrow widths get mobile layouts,
+    /// which often hide navigation behind menus
+    #[arg(long, value_parser = clap::value_parser!(i64).range(1..))]
+    width: Option<i64>,
+
+    /// Height of the emulated window in CSS pixels, taking precedence
+    /// over `--device` [default: 800]
+    #[arg(long, value_parser = clap::value_parser!(i64).range(1..))]
+    height: Option<i64>,
+
+    /// Allow fetching from hosts matching this pattern (e.g.
+    /// `*.example.com`). Can be repeated
+    #[arg(long, value_name = "HOST")]
+    allow_host: Vec<String>,
+
+    /// Refuse to fetch from hosts matching this pattern (e.g.
+    /// `*.example.com`). Can be repeated
+    #[arg(long, value_name = "HOST")]
+    deny_host: Vec<String>,
+
+    /// Also fetch and convert this URL and print a unified diff of the
+    /// two outputs instead of the markdown
+    #[arg(long, value_name = "URL2")]
+    diff: Option<String>,
+
+    /// Keep the page open and explore it with commands (`print`,
+    /// `selector <css>`, `eval <js>`, `reload`) instead of converting it
+    /// once
+    #[arg(long, conflicts_with_all = ["diff", "output", "output_dir"])]
+    interactive: bool,
+
+    /// Exit with status 1 when `--diff` finds differences
+    #[arg(long, requires = "diff")]
+    fail_on_diff: bool,
+
+    /// Follow next-page links and append each page to the first, to
+    /// reassemble multi-page articles. Pass `rel=next` or a CSS selector
+    /// for the link
+    #[arg(long, value_name = "SELECTOR_OR_REL", conflicts_with = "stream")]
+    follow_next: Option<String>,
+
+    /// Most pages to load per URL with `--follow-next`
+    #[arg(long, value_name = "N", default_value_t = 10, requires = "follow_next")]
+    max_pages: usize,
+
+    /// Skip pages that fail to load or convert instead of stopping at the
+    /// first error
+    #[arg(long)]
+    continue_on_error: bool,
+
+    /// Stop the run after this many seconds of wall-clock time, closing
+    /// the browser and printing whatever completed
+    #[arg(long, value_name = "SECONDS")]
+    max_runtime: Option<u64>,
+
+    /// Estimate the reading time in minutes, added to the frontmatter or
+    /// JSON lines record when used, otherwise appended to the markdown
+    #[arg(long)]
+    reading_time: bool,
+
+    /// Reading speed used by `--reading-time`
+    #[arg(long, value_name = "WPM", default_value_t = 200)]
+    words_per_minute: usize,
+
+    /// Print the value at this JSON pointer (e.g. `/offers/price`) in each
+    /// of the page's JSON-LD blocks instead of converting the page. Can't
+    /// be combined with `--format jsonl`
+    #[arg(long, value_name = "JSON_POINTER")]
+    extract: Option<String>,
+
+    /// Include the page's HTTP response headers, as a comment block before
+    /// the markdown or a field of JSON lines records
+    #[arg(long)]
+    dump_headers: bool,
+
+    /// Show cookies and authorization headers in `--dump-headers` output
+    /// instead of redacting them
+    #[arg(long, requires = "dump_headers")]
+    include_sensitive_headers: bool,
+
+    /// Text to put before each page's markdown, or `@file` to read it
+    /// from a file
+    #[arg(long, value_name = "TEXT", value_parser = output::parse_text)]
+    prepend: Option<String>,
+
+    /// Text to put after each page's markdown, or `@file` to read it from
+    /// a file
+    #[arg(long, value_name = "TEXT", value_parser = output::parse_text)]
+    append: Option<String>,
+
+    /// Render each page through this template instead of printing the
+    /// markdown as is. `{{markdown}}`, `{{title}}`, `{{url}}` and
+    /// `{{frontmatter}}` are replaced with the page's values
+    #[arg(long, value_name = "FILE", value_parser = read_file, conflicts_with = "stream")]
+    template: Option<String>,
+
+    /// Write the markdown to this file instead of printing it
+    #[arg(short, long, value_name = "PATH", conflicts_with_all = ["output_dir", "diff", "stream"])]
+    output: Option<std::path::PathBuf>,
+
+    /// Save the markdown to a file named after the URL in this directory
+    /// instead of printing it. Not available with `--format jsonl`
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["diff", "stream"])]
+    output_dir: Option<std::path::PathBuf>,
+
+    /// Split each page at headings of this level and write every section
+    /// to its own file in `--output-dir`, named after the heading, with
+    /// content before the first heading in `index.md`
+    #[arg(long, value_name = "LEVEL", requires = "output_dir", value_parser = clap::value_parser!(u8).range(1..=6))]
+    split_by_heading: Option<u8>,
+
+    /// Gzip-compress files written to `--output-dir` (`.md.gz`)
+    #[arg(long, requires = "output_dir")]
+    gzip: bool,
+
+    /// After the run, write page, failure, timeout, challenge and output
+    /// byte counts and fetch durations to this file in the Prometheus
+    /// text format
+    #[arg(long, value_name = "PATH", conflicts_with = "interactive")]
+    metrics_file: Option<std::path::PathBuf>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Markdown)]
+    format: OutputFormat,
+
+    /// Prepend a frontmatter block with the page URL and title
+    #[arg(long)]
+    frontmatter: bool,
+
+    /// Serialization of the frontmatter block
+    #[arg(long, value_enum, default_value_t = frontmatter::FrontmatterFormat::Yaml)]
+    frontmatter_format: frontmatter::FrontmatterFormat,
+
+    /// Markers used for italic and bold text
+    #[arg(long, value_enum, default_value_t = style::EmphasisStyle::Asterisk)]
+    emphasis_style: style::EmphasisStyle,
+
+    /// Characters used for horizontal rules
+    #[arg(long, value_enum, default_value_t = style::HrStyle::Dashes)]
+    hr_style: style::HrStyle,
+
+    /// Comma-separated elements to drop before converting HTML, replacing
+    /// the default set (script, style, footer, img, svg, iframe, head,
+    /// link)
+    #[arg(long, value_name = "TAGS", value_delimiter = ',')]
+    skip_tags: Vec<String>,
+
+    /// Comma-separated elements to keep when converting HTML, taking them
+    /// out of the skipped set, e.g. `img,footer`
+    #[arg(long, value_name = "TAGS", value_delimiter = ',')]
+    keep_tags: Vec<String>,
+
+    /// Markup for inserted (`<ins>`) text (axtree mode)
+    #[arg(long, value_enum, default_value_t = style::InsertionStyle::Plus)]
+    insertion_style: style::InsertionStyle,
+
+    /// Merge bold, italic and inserted runs that are only separated by
+    /// whitespace, e.g. `**Hello** **world**` becomes `**Hello world**`
+    /// (axtree mode)
+    #[arg(long)]
+    merge_inline_runs: bool,
+
+    /// Layout for runs of adjacent links such as navigation and tag
+    /// clouds (axtree mode)
+    #[arg(long, value_enum, default_value_t = style::LinkRunStyle::Inline)]
+    link_run_style: style::LinkRunStyle,
+
     #[cfg(feature = "llm")]
     /// LLM API endpoint for markdown cleanup
     #[arg(short, long)]
@@ -139,58 +524,466 @@ struct Cli {
     api_key: String,
 }
 
+/// Read a file named on the command line, such as a user script or
+/// template
+fn read_file(path: &str) -> Result<String, String> {
+    std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))
+}
+
+/// Parse an internal role override of the form `VALUE=NAME`
+fn parse_internal_role(s: &str) -> Result<(i64, String), String> {
+    let (value, name) = s.split_once('=')
+        .ok_or_else(|| format!("expected VALUE=NAME, got `{}`", s))?;
+    let value = value.trim().parse::<i64>()
+        .map_err(|e| format!("invalid role value `{}`: {}", value, e))?;
+    Ok((value, name.trim().to_string()))
+}
+
+impl Cli {
+    /// Build the fetch settings from the command line
+    fn fetch_config(&self) -> FetchConfig {
+        let mut internal_roles = axtree::InternalRoleMap::default();
+        for (value, name) in &self.internal_role {
+            internal_roles.insert(*value, name);
+        }
+        FetchConfig {
+            axtree: self.axtree,
+            ast: self.format == OutputFormat::Ast,
+            raw_tree: self.format == OutputFormat::Json,
+            dot: self.format == OutputFormat::Dot,
+            chrome_path: self.chrome_path.clone(),
+            headless: self.headless,
+            connect: self.connect.clone(),
+            proxy: self.proxy.clone(),
+            device: self.device,
+            viewport: device::Viewport { width: self.width, height: self.height },
+            user_agent: self.user_agent.clone(),
+            lang: self.lang.clone(),
+            user_script: self.user_script.clone(),
+            wait_for: self.wait_for.clone(),
+            delay_before_capture: self.delay_before_capture.map(Duration::from_millis),
+            fail_on_challenge: self.fail_on_challenge,
+            captcha_timeout: self.captcha_timeout.map(Duration::from_secs),
+            captcha_poll_interval: Duration::from_millis(self.captcha_poll_interval),
+            dump_headers: self.dump_headers,
+            include_sensitive_headers: self.include_sensitive_headers,
+            inline_images: self.inline_images,
+            max_image_bytes: self.max_image_bytes,
+            raw_entities: self.raw_entities,
+            strict_tree: self.strict_tree,
+            fail_on_empty: self.fail_on_empty,
+            follow_next: self.follow_next.clone(),
+            max_pages: self.max_pages,
+            host_filter: Some(links::HostFilter {
+                seed_hosts: self.urls.iter().chain(self.diff.iter()).filter_map(|url| links::host_of(url)).collect(),
+                allow: self.allow_host.clone(),
+                deny: self.deny_host.clone(),
+            }),
+            new_after: self.new_after.map(Duration::from_secs),
+            exclude_selectors: self.selector_exclude.clone(),
+            emphasis_style: self.emphasis_style,
+            hr_style: self.hr_style,
+            skip_tags: html::skip_tags(&self.skip_tags, &self.keep_tags),
+            convert: axtree::ConvertOptions {
+                normalize_links: self.normalize_links,
+                columns: self.columns,
+                no_footer_marker: self.no_footer_marker,
+                internal_roles,
+                flatten: self.flatten,
+                footnotes: self.footnotes,
+                strip_nav_links: self.strip_nav_links,
+                hr_style: self.hr_style,
+                emphasis_style: self.emphasis_style,
+                debug_roles: self.debug_roles,
+                escape_markdown: self.escape_markdown,
+                raw_entities: self.raw_entities,
+                include_aria_hidden: self.include_aria_hidden,
+                link_run_style: self.link_run_style,
+                insertion_style: self.insertion_style,
+                merge_inline_runs: self.merge_inline_runs,
+                callouts: self.callouts,
+                toc: self.toc || self.toc_depth.is_some(),
+                toc_depth: self.toc_depth,
+                slug_style: self.slug_style,
+                prettify_whitespace: self.prettify_whitespace,
+                min_text_length: self.min_text_length,
+                max_bytes: self.max_bytes,
+                max_depth: self.max_depth,
+                merge_subtitles: self.merge_subtitles,
+                dedupe_adjacent_links: self.dedupe_adjacent_links,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Estimate the reading time of converted markdown when requested
+fn reading_time(cli: &Cli, markdown: &str) -> Option<usize> {
+    cli.reading_time
+        .then(|| stats::reading_time_minutes(stats::word_count(markdown), cli.words_per_minute))
+}
+
+/// Render the frontmatter block when requested
+fn frontmatter_block(cli: &Cli, metadata: &frontmatter::Metadata) -> Result<Option<String>, HeadfulError> {
+    if !cli.frontmatter || cli.format != OutputFormat::Markdown {
+        return Ok(None);
+    }
+    frontmatter::render(metadata, cli.frontmatter_format)
+        .map(Some)
+        .map_err(|e| HeadfulError::Conversion(e.to_string()))
+}
+
+/// Fill a `--template` with the page's values. The frontmatter
+/// placeholder is available whether or not `--frontmatter` is given.
+fn render_template(
+    cli: &Cli,
+    template: &str,
+    metadata: &frontmatter::Metadata,
+    markdown: &str,
+) -> Result<String, HeadfulError> {
+    let block = frontmatter::render(metadata, cli.frontmatter_format)
+        .map_err(|e| HeadfulError::Conversion(e.to_string()))?;
+    let values = [
+        ("markdown", markdown),
+        ("title", metadata.title.as_deref().unwrap_or_default()),
+        ("url", metadata.url.as_str()),
+        ("frontmatter", block.as_str()),
+    ];
+    // The newline ending the template file is added back when printing
+    let rendered = template::render(template, &values).map_err(HeadfulError::Conversion)?;
+    Ok(rendered.trim_end_matches('\n').to_string())
+}
+
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cli = Cli::parse();
-
-    // Create a headful chromium browser and the handler to drive the
-    // browser via websocket
-    let (mut browser, mut handler) =
-        Browser::launch(BrowserConfig::builder().with_head().build()?).await?;
-    let handle = task::spawn(async move {
-        while let Some(h) = handler.next().await {
-            if h.is_err() {
+async fn main() {
+    let cli = Cli::try_parse().unwrap_or_else(|e| {
+        let _ = e.print();
+        std::process::exit(if e.use_stderr() { error::EXIT_USAGE } else { 0 })
+    });
+    if let Err(e) = run(cli).await {
+        eprintln!("Error: {}", e);
+        std::process::exit(e.exit_code());
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), HeadfulError> {
+    if cli.format == OutputFormat::Jsonl {
+        if cli.extract.is_some() {
+            return Err(HeadfulError::Usage("--extract prints values, not JSON lines records".to_string()));
+        }
+        if cli.output.is_some() || cli.output_dir.is_some() {
+            let reason = "--format jsonl prints records as pages complete, redirect stdout to save them";
+            return Err(HeadfulError::Usage(reason.to_string()));
+        }
+    }
+    if cli.urls.len() > 1 {
+        if cli.diff.is_some() {
+            return Err(HeadfulError::Usage("--diff compares a single URL".to_string()));
+        }
+        if cli.output.is_some() {
+            let reason = "--output takes a single URL, use --output-dir for several";
+            return Err(HeadfulError::Usage(reason.to_string()));
+        }
+        if cli.interactive {
+            return Err(HeadfulError::Usage("--interactive explores a single URL".to_string()));
+        }
+    }
+
+    // URLs on hosts the filter doesn't allow are skipped, not fatal
+    let config = cli.fetch_config();
+    let urls: Vec<&String> = cli
+        .urls
+        .iter()
+        .chain(cli.diff.iter())
+        .filter(|url| match config.host_filter.as_ref().map(|hosts| hosts.check(url)) {
+            Some(Err(reason)) => {
+                eprintln!("Skipping {}: {}", url, reason);
+                false
+            }
+            _ => true,
+        })
+        .collect();
+    if urls.is_empty() {
+        return Ok(());
+    }
+
+    // Create a chromium browser, headful unless `--headless` is given (or
+    // connect to a running one) and the handler to drive the browser via
+    // websocket
+    let session = fetch::Session::start(&config).await?;
+
+    if cli.interactive {
+        let explored = explore(&config, &session, urls[0]).await;
+        session.close().await?;
+        return explored;
+    }
+
+    // Fetch the pages, then clean up even when a page failed, leaving a
+    // browser we connected to running for its owner
+    let mut metrics = metrics::Metrics::default();
+    let captured = capture_all(&cli, &config, &session, &urls, &mut metrics).await;
+    session.close().await?;
+
+    // Metrics are written even when a page failed, since that is when
+    // they are most useful
+    let written = match captured {
+        Ok((captures, cutoff)) => write_all(&cli, &config, captures, &mut metrics)
+            .await
+            .and_then(|differs| cutoff.map_or(Ok(differs), Err)),
+        Err(e) => Err(e),
+    };
+    // A failed page is the error to report, even if saving metrics failed
+    let saved = cli.metrics_file.as_ref().map_or(Ok(()), |path| metrics.write(path));
+    let differs = written?;
+    saved?;
+    if differs && cli.fail_on_diff {
+        std::process::exit(error::EXIT_DIFFERENT);
+    }
+    Ok(())
+}
+
+/// Print or save the captured pages, or their diff with `--diff`.
+/// Returns whether the diffed pages differ.
+async fn write_all(
+    cli: &Cli,
+    config: &FetchConfig,
+    mut captures: Vec<(&str, fetch::Capture)>,
+    metrics: &mut metrics::Metrics,
+) -> Result<bool, HeadfulError> {
+    if let Some(ref other_url) = cli.diff
+        && captures.len() == 2
+    {
+        let (url, capture) = captures.remove(0);
+        let (_, other) = captures.remove(0);
+        let before = fetch::convert_capture(config, capture)?;
+        let after = fetch::convert_capture(config, other)?;
+        let Some(unified) = diff::unified(&before, &after, url, other_url) else {
+            return Ok(false);
+        };
+        print!("{}", unified);
+        metrics.record_output(unified.len());
+        return Ok(true);
+    }
+
+    // Pages printed together are divided by a rule and headed by their
+    // URL. JSON pages are collected in an array instead, and DOT graphs
+    // simply follow one another.
+    let together = cli.urls.len() > 1 && cli.output_dir.is_none();
+    let headed = together && !cli.format.is_data();
+    let array = together && config.emits_json();
+    if array {
+        println!("[");
+    }
+    let mut printed = 0;
+    let mut names = output::FileNames::default();
+    for (url, capture) in captures {
+        let pages = 1 + capture.continued.len();
+        let lead = match (headed, printed > 0) {
+            (true, false) => format!("# {}\n\n", url),
+            (true, true) => format!("\n{}\n\n# {}\n\n", cli.hr_style.rule(), url),
+            (false, true) if array => ",\n".to_string(),
+            (false, _) => String::new(),
+        };
+        match write_page(cli, config, url, capture, &lead, &mut names).await {
+            Ok(bytes) => {
+                metrics.record_output(bytes);
+                printed += 1;
+            }
+            Err(e @ HeadfulError::Conversion(_)) => {
+                metrics.record_conversion_failure(pages);
+                if !cli.continue_on_error {
+                    return Err(e);
+                }
+                eprintln!("Skipping {}: {}", url, e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    if array {
+        println!("]");
+    }
+    Ok(false)
+}
+
+/// Open the page and run REPL commands against it from stdin
+async fn explore(config: &FetchConfig, session: &fetch::Session, url: &str) -> Result<(), HeadfulError> {
+    let page = fetch::open_live_page(&session.browser, config, url).await?;
+    repl::run(io::stdin().lock(), io::stdout(), |command| repl::execute(&page, config, command)).await?;
+    Ok(())
+}
+
+/// Capture each page with the session's browser, writing JSON lines
+/// records as pages complete. Also returns the timeout that cut the run
+/// short when `--max-runtime` ran out, so it's reported after the
+/// completed pages are written.
+async fn capture_all<'a>(
+    cli: &Cli,
+    config: &FetchConfig,
+    session: &fetch::Session,
+    urls: &[&'a String],
+    metrics: &mut metrics::Metrics,
+) -> Result<(Vec<(&'a str, fetch::Capture)>, Option<HeadfulError>), HeadfulError> {
+    // Fetch the pages with the same browser, stopping early if the run is
+    // out of time
+    let deadline = deadline::Deadline::after(cli.max_runtime.map(Duration::from_secs));
+    let mut captures = Vec::new();
+    let mut cutoff = None;
+    let sink = sink::OutputSink::new(io::stdout());
+    for (index, url) in urls.iter().enumerate() {
+        let started = Instant::now();
+        let fetched = deadline.run(fetch::capture_page(&session.browser, config, url)).await;
+        if let Some(ref result) = fetched {
+            metrics.record_fetch(started.elapsed(), result);
+        }
+        if let Some(Ok(ref capture)) = fetched {
+            report_capture(capture);
+        }
+        match fetched {
+            Some(Err(e)) if cli.continue_on_error => {
+                eprintln!("Skipping {}: {}", url, e);
+                sink.skip(index)?;
+            }
+            Some(Ok(capture)) if cli.format == OutputFormat::Jsonl => {
+                let pages = 1 + capture.continued.len();
+                match jsonl_line(cli, config, url, capture) {
+                    Ok(line) => {
+                        metrics.record_output(line.len());
+                        sink.submit(index, line)?;
+                    }
+                    Err(e @ HeadfulError::Conversion(_)) => {
+                        metrics.record_conversion_failure(pages);
+                        if !cli.continue_on_error {
+                            return Err(e);
+                        }
+                        eprintln!("Skipping {}: {}", url, e);
+                        sink.skip(index)?;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            Some(capture) => captures.push((url.as_str(), capture?)),
+            None => {
+                // The page that was cut off counts as timed out
+                let timeout = Err::<fetch::Capture, _>(HeadfulError::Timeout(format!(
+                    "after --max-runtime of {}s; {} of {} pages completed",
+                    cli.max_runtime.unwrap_or_default(),
+                    index,
+                    urls.len(),
+                )));
+                metrics.record_fetch(started.elapsed(), &timeout);
+                cutoff = timeout.err();
                 break;
             }
         }
-    });
+    }
+    Ok((captures, cutoff))
+}
 
-    // Fetch the page
-    let page = browser.new_page(&cli.url).await?;
-    let html = page.wait_for_navigation().await?.content().await?;
-    let axt_value = page.execute(GetFullAxTree).await?;
-
-    // Clean up
-    browser.close().await?;
-    let _ = handle.await;
-
-    // Convert to markdown using accessibility tree or HTML
-    let markdown_content = if cli.axtree {
-        // Parse the accessibility tree from JSON value
-        let axt_json = serde_json::to_string(&axt_value.result)?;
-        let axt: axtree::AxTree = serde_json::from_str(&axt_json)?;
-        eprintln!("Converted accessibility tree with {} nodes", axt.nodes.len());
-        axtree::axtree_to_markdown(&axt)
-    } else {
-        // Convert HTML to markdown
-        let converter = HtmlToMarkdown::builder()
-            .skip_tags(vec!["script", "style", "footer", "img", "svg", "iframe", "head", "link"])
-            .build();
-        let markdown = converter.convert(&html)?;
-
-        // Naive captcha detection and wait for the user to indicate they
-        // completed it (only for HTML conversion)
-        let markdown = if markdown.contains("CAPTCHA") {
-            // This is blocking!
-            wait_for_enter("Please complete the CAPTCHA and press return to continue")?;
-            let html_after_captcha = page.wait_for_navigation().await?.content().await?;
-            converter.convert(&html_after_captcha)?
-        } else {
-            markdown
-        };
+/// Print the warnings raised while capturing a page and the pages after
+/// it, with the size of each accessibility tree captured
+fn report_capture(capture: &fetch::Capture) {
+    for page in std::iter::once(capture).chain(&capture.continued) {
+        for warning in &page.warnings {
+            eprintln!("Warning: {}", warning);
+        }
+        if let Some(ref axt) = page.axt {
+            eprintln!("Captured accessibility tree with {} nodes", axt.nodes.len());
+        }
+    }
+}
+
+/// Convert a captured page to its JSON lines record
+fn jsonl_line(cli: &Cli, config: &FetchConfig, url: &str, mut capture: fetch::Capture) -> Result<Vec<u8>, HeadfulError> {
+    let title = capture.title.take();
+    let image = capture.image.take();
+    let status = capture.status;
+    let headers = capture.headers.take();
+    let markdown = fetch::convert_capture(config, capture)?;
+    let minutes = reading_time(cli, &markdown);
+    let markdown = output::wrap(&markdown, cli.prepend.as_deref(), cli.append.as_deref());
+    let record = jsonl::Record {
+        url,
+        markdown: &markdown,
+        title: title.as_deref(),
+        image: image.as_deref(),
+        status,
+        reading_time: minutes,
+        headers: headers.as_ref(),
+    };
+    let mut line = Vec::new();
+    jsonl::write_record(&mut line, &record)?;
+    Ok(line)
+}
+
+/// Convert a captured page and print it after `lead` (its URL heading
+/// when printed with other pages), or save it when an output file or
+/// directory is given, under a name from `names`. Returns the number of
+/// bytes written.
+async fn write_page(
+    cli: &Cli,
+    config: &FetchConfig,
+    url: &str,
+    mut capture: fetch::Capture,
+    lead: &str,
+    names: &mut output::FileNames,
+) -> Result<usize, HeadfulError> {
+    // Print values from the page's JSON-LD instead of converting it
+    if let Some(ref pointer) = cli.extract {
+        let blocks = jsonld::blocks(&capture.html);
+        let values = jsonld::extract(&blocks, pointer);
+        if values.is_empty() {
+            eprintln!("No value at {} in {} JSON-LD block(s)", pointer, blocks.len());
+        }
+        print!("{}", lead);
+        let mut written = lead.len();
+        for value in values {
+            let value = jsonld::display(value);
+            println!("{}", value);
+            written += value.len() + 1;
+        }
+        return Ok(written);
+    }
 
-        markdown
+    let mut metadata = frontmatter::Metadata {
+        url: url.to_string(),
+        title: capture.title.take(),
+        image: capture.image.take(),
+        reading_time: None,
     };
+    let headers_block = match capture.headers.take() {
+        Some(ref headers) if cli.format == OutputFormat::Markdown => headers::comment_block(headers),
+        _ => String::new(),
+    };
+
+    // Write lines as they are converted instead of buffering the whole
+    // document. The reading time isn't known up front so it is skipped.
+    if cli.stream
+        && cli.format == OutputFormat::Markdown
+        && let Some(axt) = capture.axt.take()
+    {
+        if cli.strict_tree {
+            fetch::check_tree(&axt)?;
+        }
+        fetch::check_empty(config, &axt)?;
+        let options = fetch::convert_options(config, &mut capture);
+        let mut header = lead.to_string();
+        header.push_str(&frontmatter_block(cli, &metadata)?.unwrap_or_default());
+        header.push_str(&headers_block);
+        if let Some(ref prepend) = cli.prepend {
+            header.push_str(&format!("{}\n\n", prepend.trim_end()));
+        }
+        print!("{}", header);
+        let mut body = metrics::CountingWriter::new(io::stdout().lock());
+        axtree::axtree_to_markdown_writer(&axt, &options, &mut body)?;
+        let mut footer = String::from("\n");
+        if let Some(ref append) = cli.append {
+            footer.push_str(&format!("\n{}\n", append.trim_end()));
+        }
+        print!("{}", footer);
+        return Ok(header.len() + body.count() + footer.len());
+    }
+
+    let markdown_content = fetch::convert_capture(config, capture)?;
 
     // Clean up with LLM if feature is enabled
     #[cfg(feature = "llm")]
@@ -198,6 +991,54 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         markdown_content = cleanup_with_llm(&markdown_content, &cli.llm_endpoint, &cli.api_key).await?;
     }
 
-    println!("{}", markdown_content);
-    Ok(())
+    let minutes = reading_time(cli, &markdown_content);
+    let markdown_content = if cli.format.is_data() {
+        markdown_content
+    } else {
+        output::wrap(&markdown_content, cli.prepend.as_deref(), cli.append.as_deref())
+    };
+    metadata.reading_time = minutes;
+    let document = match cli.template {
+        Some(ref template) => render_template(cli, template, &metadata, &markdown_content)?,
+        None => {
+            let frontmatter = frontmatter_block(cli, &metadata)?;
+            let mut document = frontmatter.clone().unwrap_or_default();
+            document.push_str(&headers_block);
+            document.push_str(&markdown_content);
+            if frontmatter.is_none()
+                && let Some(minutes) = minutes
+                && cli.format == OutputFormat::Markdown
+            {
+                document.push_str(&format!("\n\nReading time: {} min", minutes));
+            }
+            document
+        }
+    };
+
+    let mut written = document.len() + 1;
+    if let Some(ref path) = cli.output {
+        std::fs::write(path, format!("{}\n", document))?;
+    } else if let Some(ref dir) = cli.output_dir
+        && let Some(level) = cli.split_by_heading
+    {
+        // Sections of several pages go in a directory per page so they
+        // don't overwrite each other
+        let dir = if cli.urls.len() > 1 {
+            let name = names.name(url, false);
+            dir.join(name.trim_end_matches(".md"))
+        } else {
+            dir.clone()
+        };
+        let sections = output::split_sections(&document, level as usize, cli.slug_style);
+        for path in output::write_sections(&dir, &sections, cli.gzip)? {
+            eprintln!("Wrote {}", path.display());
+        }
+    } else if let Some(ref dir) = cli.output_dir {
+        let path = output::write_markdown(dir, &names.name(url, cli.gzip), &document, cli.gzip)?;
+        eprintln!("Wrote {}", path.display());
+    } else {
+        println!("{}{}", lead, document);
+        written += lead.len();
+    }
+    Ok(written)
 }
@@ -0,0 +1,169 @@
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::error::HeadfulError;
+use crate::fetch::Capture;
+
+/// Upper bounds in seconds of the fetch duration histogram buckets
+const DURATION_BUCKETS: [f64; 8] = [0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0];
+
+/// Counters and fetch timings accumulated over a run, written in the
+/// Prometheus text exposition format
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metrics {
+    pub pages_fetched: u64,
+    pub failures: u64,
+    pub timeouts: u64,
+    pub challenges: u64,
+    pub bytes_output: u64,
+    /// Number of fetches that took at most each bucket's bound
+    duration_buckets: [u64; DURATION_BUCKETS.len()],
+    duration_sum: f64,
+    duration_count: u64,
+}
+
+impl Metrics {
+    /// Count a finished fetch and how long it took. A page that showed a
+    /// challenge counts as challenged whether or not it was solved.
+    pub fn record_fetch(&mut self, elapsed: Duration, result: &Result<Capture, HeadfulError>) {
+        match result {
+            Ok(capture) => {
+                let pages = std::iter::once(capture).chain(&capture.continued);
+                for page in pages {
+                    self.pages_fetched += 1;
+                    self.challenges += u64::from(page.challenge.is_some());
+                }
+            }
+            Err(e) => {
+                self.failures += 1;
+                match e {
+                    HeadfulError::Timeout(_) => self.timeouts += 1,
+                    HeadfulError::ChallengeEncountered { .. } | HeadfulError::ChallengeUnresolved { .. } => {
+                        self.challenges += 1
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let seconds = elapsed.as_secs_f64();
+        for (count, bound) in self.duration_buckets.iter_mut().zip(DURATION_BUCKETS) {
+            if seconds <= bound {
+                *count += 1;
+            }
+        }
+        self.duration_sum += seconds;
+        self.duration_count += 1;
+    }
+
+    /// Count a fetch whose pages then failed to convert as a failure
+    /// instead of `pages` pages fetched
+    pub fn record_conversion_failure(&mut self, pages: usize) {
+        self.pages_fetched = self.pages_fetched.saturating_sub(pages as u64);
+        self.failures += 1;
+    }
+
+    /// Count bytes written to stdout or files
+    pub fn record_output(&mut self, bytes: usize) {
+        self.bytes_output += bytes as u64;
+    }
+
+    /// Render the metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        let counters = [
+            ("headful_pages_fetched_total", "Pages fetched successfully", self.pages_fetched),
+            ("headful_fetch_failures_total", "Pages that failed to fetch", self.failures),
+            ("headful_fetch_timeouts_total", "Fetches that timed out", self.timeouts),
+            ("headful_challenges_total", "Pages that showed a challenge", self.challenges),
+            ("headful_output_bytes_total", "Bytes of converted output written", self.bytes_output),
+        ];
+        for (name, help, value) in counters {
+            let _ = write!(text, "# HELP {name} {help}\n# TYPE {name} counter\n{name} {value}\n");
+        }
+
+        let name = "headful_fetch_duration_seconds";
+        let _ = write!(text, "# HELP {name} Time taken to fetch a page\n# TYPE {name} histogram\n");
+        for (count, bound) in self.duration_buckets.iter().zip(DURATION_BUCKETS) {
+            let _ = writeln!(text, "{name}_bucket{{le=\"{bound}\"}} {count}");
+        }
+        let _ = writeln!(text, "{name}_bucket{{le=\"+Inf\"}} {}", self.duration_count);
+        let _ = writeln!(text, "{name}_sum {}", self.duration_sum);
+        let _ = writeln!(text, "{name}_count {}", self.duration_count);
+        text
+    }
+
+    /// Write the rendered metrics to a file, replacing what was there
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        std::fs::write(path, self.render())
+    }
+}
+
+/// Writer that counts the bytes passing through it, for output that is
+/// streamed instead of built up front
+pub struct CountingWriter<W> {
+    inner: W,
+    count: usize,
+}
+
+impl<W: Write> CountingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        CountingWriter { inner, count: 0 }
+    }
+
+    /// Bytes written so far
+    pub fn count(&self) -> usize {
+        self.count
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::challenge::ChallengeKind;
+
+    #[test]
+    fn test_metrics_file() {
+        let mut metrics = Metrics::default();
+        metrics.record_fetch(Duration::from_millis(250), &Ok(Capture::default()));
+        let challenged = Capture { challenge: Some(ChallengeKind::Captcha), ..Default::default() };
+        metrics.record_fetch(Duration::from_secs(3), &Ok(challenged));
+        metrics.record_fetch(Duration::from_secs(30), &Err(HeadfulError::Timeout("navigating".to_string())));
+        let paginated = Capture { continued: vec![Capture::default()], ..Default::default() };
+        metrics.record_fetch(Duration::from_secs(1), &Ok(paginated));
+        metrics.record_conversion_failure(2);
+        metrics.record_output(120);
+        metrics.record_output(80);
+
+        let path = std::env::temp_dir().join(format!("headful-metrics-{}.prom", std::process::id()));
+        metrics.write(&path).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(text.contains("# TYPE headful_pages_fetched_total counter\nheadful_pages_fetched_total 2\n"));
+        assert!(text.contains("\nheadful_fetch_failures_total 2\n"));
+        assert!(text.contains("\nheadful_fetch_timeouts_total 1\n"));
+        assert!(text.contains("\nheadful_challenges_total 1\n"));
+        assert!(text.contains("\nheadful_output_bytes_total 200\n"));
+        assert!(text.contains("# TYPE headful_fetch_duration_seconds histogram\n"));
+        assert!(text.contains("\nheadful_fetch_duration_seconds_bucket{le=\"0.5\"} 1\n"));
+        assert!(text.contains("\nheadful_fetch_duration_seconds_bucket{le=\"5\"} 3\n"));
+        assert!(text.contains("\nheadful_fetch_duration_seconds_bucket{le=\"+Inf\"} 4\n"));
+        assert!(text.contains("\nheadful_fetch_duration_seconds_sum 34.25\n"));
+        assert!(text.ends_with("headful_fetch_duration_seconds_count 4\n"));
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+use crate::slug::{SlugStyle, Slugger};
+
+/// Name of the file a page is saved to in an output directory, built from
+/// its host and path, plus a hash of the query string when there is one
+/// so `?page=1` and `?page=2` get their own files
+pub fn file_name(url: &str, gzip: bool) -> String {
+    let (stem, query) = match url::Url::parse(url) {
+        Ok(parsed) => (
+            format!("{}{}", parsed.host_str().unwrap_or_default(), parsed.path()),
+            parsed.query().map(short_hash),
+        ),
+        Err(_) => (url.to_string(), None),
+    };
+    let mut name: String = stem
+        .trim_end_matches('/')
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+        .collect();
+    if name.is_empty() {
+        name.push_str("index");
+    }
+    if let Some(hash) = query {
+        name.push('-');
+        name.push_str(&hash);
+    }
+    name.push_str(if gzip { ".md.gz" } else { ".md" });
+    name
+}
+
+/// Hash text into 8 hex digits with FNV-1a, which unlike `DefaultHasher`
+/// gives the same file names from one build to the next
+fn short_hash(text: &str) -> String {
+    let hash = text.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{:08x}", hash >> 32)
+}
+
+/// Names the files of the pages in a run, suffixing a name with a hash
+/// of the whole URL when another URL already took it (`/a/b` and `/a_b`
+/// both map to `a_b.md`), so pages never overwrite each other
+#[derive(Debug, Default)]
+pub struct FileNames {
+    urls: HashMap<String, String>,
+}
+
+impl FileNames {
+    /// The file name for a URL, the same each time it's asked for
+    pub fn name(&mut self, url: &str, gzip: bool) -> String {
+        let name = file_name(url, gzip);
+        match self.urls.get(&name) {
+            Some(taken) if taken != url => {
+                let extension = if gzip { ".md.gz" } else { ".md" };
+                let stem = name.trim_end_matches(extension);
+                let unique = format!("{}-{}{}", stem, short_hash(url), extension);
+                self.urls.insert(unique.clone(), url.to_string());
+                unique
+            }
+            _ => {
+                self.urls.insert(name.clone(), url.to_string());
+                name
+            }
+        }
+    }
+}
+
+/// Write converted markdown into the file `name` in `dir`,
+/// gzip-compressed when asked to. Returns the path written.
+pub fn write_markdown(dir: &Path, name: &str, markdown: &str, gzip: bool) -> io::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(name);
+    write_file(&path, markdown, gzip)?;
+    Ok(path)
+}
+
+/// Write markdown to a file, gzip-compressed when asked to
+fn write_file(path: &Path, markdown: &str, gzip: bool) -> io::Result<()> {
+    if gzip {
+        let mut encoder = GzEncoder::new(File::create(path)?, Compression::default());
+        encoder.write_all(markdown.as_bytes())?;
+        encoder.finish()?;
+        Ok(())
+    } else {
+        std::fs::write(path, markdown)
+    }
+}
+
+/// Split markdown into sections at headings of the given level. Each
+/// section runs from its heading to the next one and is named after the
+/// heading's slug; content before the first heading is named `index`.
+/// Headings inside fenced code blocks are ignored.
+pub fn split_sections(markdown: &str, level: usize, style: SlugStyle) -> Vec<(String, String)> {
+    let marker = format!("{} ", "#".repeat(level));
+    // Reserve `index` so a heading with that text doesn't overwrite it
+    let mut slugger = Slugger::new(style);
+    slugger.slug("index");
+
+    let mut sections = vec![("index".to_string(), Vec::new())];
+    let mut in_fence = false;
+    for line in markdown.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+        if !in_fence && let Some(text) = line.strip_prefix(&marker) {
+            let slug = match slugger.slug(text) {
+                slug if slug.is_empty() => slugger.slug("section"),
+                slug => slug,
+            };
+            sections.push((slug, Vec::new()));
+        }
+        if let Some((_, lines)) = sections.last_mut() {
+            lines.push(line);
+        }
+    }
+
+    sections.into_iter()
+        .map(|(name, lines)| (name, lines.join("\n").trim().to_string()))
+        .filter(|(_, content)| !content.is_empty())
+        .collect()
+}
+
+/// Write each section from `split_sections` to its own file in `dir`.
+/// Returns the paths written.
+pub fn write_sections(dir: &Path, sections: &[(String, String)], gzip: bool) -> io::Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(dir)?;
+    let extension = if gzip { "md.gz" } else { "md" };
+    let mut paths = Vec::new();
+    for (name, content) in sections {
+        let path = dir.join(format!("{}.{}", name, extension));
+        write_file(&path, &format!("{}\n", content), gzip)?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// Parse boilerplate text from the command line, reading it from a file
+/// when given as `@path`
+pub fn parse_text(value: &str) -> Result<String, String> {
+    match value.strip_prefix('@') {
+        Some(path) => std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e)),
+        None => Ok(value.to_string()),
+    }
+}
+
+/// Surround converted markdown with boilerplate, separated by blank lines
+pub fn wrap(markdown: &str, prepend: Option<&str>, append: Option<&str>) -> String {
+    let mut parts = Vec::new();
+    parts.extend(prepend.map(str::trim_end));
+    parts.push(markdown);
+    parts.extend(append.map(str::trim_end));
+    parts.join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(file_name("https://example.com/", false), "example.com.md");
+        assert_eq!(file_name("https://example.com/docs/intro", true), "example.com_docs_intro.md.gz");
+
+        // Each query string gets its own file
+        let first = file_name("https://example.com/list?page=1", false);
+        let second = file_name("https://example.com/list?page=2", false);
+        assert!(first.starts_with("example.com_list-") && first.ends_with(".md"), "{}", first);
+        assert_eq!(first.len(), "example.com_list-12345678.md".len());
+        assert_ne!(first, second);
+        assert_eq!(first, file_name("https://example.com/list?page=1", false));
+    }
+
+    #[test]
+    fn test_file_name_collisions() {
+        let mut names = FileNames::default();
+        assert_eq!(names.name("https://example.com/a/b", false), "example.com_a_b.md");
+        let other = names.name("https://example.com/a_b", false);
+        assert_ne!(other, "example.com_a_b.md");
+        assert!(other.starts_with("example.com_a_b-"), "{}", other);
+
+        // Asking again for a URL gives the name it already has
+        assert_eq!(names.name("https://example.com/a/b", false), "example.com_a_b.md");
+        assert_eq!(names.name("https://example.com/a_b", false), other);
+    }
+
+    #[test]
+    fn test_wrap() {
+        let wrapped = wrap("# Example Domain", Some("<!-- scraped -->\n"), Some("Source: example.com"));
+        assert_eq!(wrapped, "<!-- scraped -->\n\n# Example Domain\n\nSource: example.com");
+        assert_eq!(wrap("# Example Domain", None, None), "# Example Domain");
+    }
+
+    #[test]
+    fn test_parse_text_from_file() {
+        let path = std::env::temp_dir().join(format!("headful-append-{}.md", std::process::id()));
+        std::fs::write(&path, "Footer\n").unwrap();
+        assert_eq!(parse_text(&format!("@{}", path.display())).unwrap(), "Footer\n");
+        assert_eq!(parse_text("Header").unwrap(), "Header");
+        std::fs::remove_file(&path).unwrap();
+        assert!(parse_text(&format!("@{}", path.display())).is_err());
+    }
+
+    #[test]
+    fn test_split_sections() {
+        let markdown = "# Guide\n\nIntro.\n\n## Install\n\n```sh\n## not a heading\n```\n\n## Usage\n\nRun it.\n\n### Flags\n\nNone.";
+        let sections = split_sections(markdown, 2, SlugStyle::Github);
+        let names: Vec<&str> = sections.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["index", "install", "usage"]);
+        assert_eq!(sections[0].1, "# Guide\n\nIntro.");
+        assert_eq!(sections[1].1, "## Install\n\n```sh\n## not a heading\n```");
+        assert_eq!(sections[2].1, "## Usage\n\nRun it.\n\n### Flags\n\nNone.");
+    }
+
+    #[test]
+    fn test_write_sections() {
+        let dir = std::env::temp_dir().join(format!("headful-sections-{}", std::process::id()));
+        let markdown = "## Install\n\ncargo install headful\n\n## Usage\n\nheadful https://example.com";
+
+        let sections = split_sections(markdown, 2, SlugStyle::Github);
+        let paths = write_sections(&dir, &sections, false).unwrap();
+        let names: Vec<_> = paths.iter().map(|path| path.file_name().unwrap().to_owned()).collect();
+        assert_eq!(names, vec!["install.md", "usage.md"]);
+        assert_eq!(std::fs::read_to_string(&paths[0]).unwrap(), "## Install\n\ncargo install headful\n");
+        assert_eq!(std::fs::read_to_string(&paths[1]).unwrap(), "## Usage\n\nheadful https://example.com\n");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_gzip_round_trip() {
+        let dir = std::env::temp_dir().join(format!("headful-output-{}", std::process::id()));
+        let markdown = "# Example Domain\n\nThis domain is for use in examples.";
+
+        let path = write_markdown(&dir, &file_name("https://example.com/", true), markdown, true).unwrap();
+        assert_eq!(path.file_name().unwrap(), "example.com.md.gz");
+
+        let mut decompressed = String::new();
+        GzDecoder::new(File::open(&path).unwrap()).read_to_string(&mut decompressed).unwrap();
+        assert_eq!(decompressed, markdown);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
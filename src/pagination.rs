@@ -0,0 +1,125 @@
+use std::collections::HashSet;
+use std::future::Future;
+
+use crate::links::{self, HostFilter};
+
+/// CSS selector matching `rel=next` links, used for `--follow-next rel=next`
+const REL_NEXT_SELECTOR: &str = r#"a[rel~="next"], link[rel~="next"]"#;
+
+/// Turn a `--follow-next` value into a CSS selector for the next-page
+/// link. `rel=next` (or just `next`) matches links with that relation,
+/// anything else is used as a selector as is.
+pub fn next_selector(value: &str) -> String {
+    match value.trim() {
+        "rel=next" | "next" => REL_NEXT_SELECTOR.to_string(),
+        selector => selector.to_string(),
+    }
+}
+
+/// Script that finds the next-page link with a selector and returns its
+/// absolute URL, or null when there isn't one
+pub fn next_link_script(selector: &str) -> Result<String, serde_json::Error> {
+    let selector = serde_json::to_string(selector)?;
+    Ok(format!(
+        r#"(() => {{
+            const link = document.querySelector({selector});
+            const href = link && (link.href || link.getAttribute("href"));
+            return href ? new URL(href, location.href).href : null;
+        }})()"#
+    ))
+}
+
+/// Visit a page and each page after it, following the next-page URL
+/// returned by `visit`. Stops after `max_pages` pages, when there is no
+/// next page, when the next page is on a host `hosts` doesn't allow, or
+/// when a URL comes up again, in any equivalent form, so a cycle can't
+/// loop forever.
+pub async fn follow<T, E, F, Fut>(
+    start: &str,
+    max_pages: usize,
+    hosts: Option<&HostFilter>,
+    mut visit: F,
+) -> Result<Vec<T>, E>
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = Result<(T, Option<String>), E>>,
+{
+    let mut visited = HashSet::new();
+    let mut pages = Vec::new();
+    let mut next = Some(start.to_string());
+    while let Some(url) = next.take() {
+        if pages.len() >= max_pages || !visited.insert(links::normalize_url(&url)) {
+            break;
+        }
+        if !pages.is_empty()
+            && let Some(Err(reason)) = hosts.map(|hosts| hosts.check(&url))
+        {
+            eprintln!("Not following next page {}: {}", url, reason);
+            break;
+        }
+        let (page, next_url) = visit(url).await?;
+        pages.push(page);
+        next = next_url;
+    }
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_follow_stops_at_cycle() {
+        let mut visits = 0;
+        let pages: Vec<usize> = follow("https://example.com/1", 10, None, |url| {
+            visits += 1;
+            let next = if url.ends_with("/1") { "https://example.com/2" } else { "https://example.com/1" };
+            async move { Ok::<_, ()>((visits, Some(next.to_string()))) }
+        })
+        .await
+        .unwrap();
+        assert_eq!(pages, vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_follow_stops_at_equivalent_url() {
+        let pages: Vec<String> = follow("https://example.com/1", 10, None, |url| {
+            let next = match url.as_str() {
+                "https://example.com/1" => "https://example.com/2",
+                _ => "HTTPS://Example.com:443/a/../1",
+            };
+            async move { Ok::<_, ()>((url, Some(next.to_string()))) }
+        })
+        .await
+        .unwrap();
+        assert_eq!(pages, vec!["https://example.com/1", "https://example.com/2"]);
+    }
+
+    #[tokio::test]
+    async fn test_follow_stops_at_denied_host() {
+        let hosts = HostFilter {
+            seed_hosts: vec!["example.com".to_string()],
+            allow: Vec::new(),
+            deny: vec!["ads.example.net".to_string()],
+        };
+        let mut visited = Vec::new();
+        let pages: Vec<String> = follow("https://example.com/1", 10, Some(&hosts), |url| {
+            visited.push(url.clone());
+            let next = match url.as_str() {
+                "https://example.com/1" => "https://example.com/2",
+                _ => "https://ads.example.net/3",
+            };
+            async move { Ok::<_, ()>((url, Some(next.to_string()))) }
+        })
+        .await
+        .unwrap();
+        assert_eq!(pages, vec!["https://example.com/1", "https://example.com/2"]);
+        assert_eq!(visited, pages);
+    }
+
+    #[test]
+    fn test_next_selector() {
+        assert_eq!(next_selector("rel=next"), REL_NEXT_SELECTOR);
+        assert_eq!(next_selector("a.pagination-next"), "a.pagination-next");
+    }
+}
@@ -0,0 +1,161 @@
+use std::future::Future;
+use std::io::{self, BufRead, Write};
+
+use chromiumoxide::Page;
+
+use crate::error::HeadfulError;
+use crate::fetch::{self, FetchConfig};
+use crate::jsonld;
+
+/// Commands accepted by the interactive REPL
+const HELP: &str = "\
+Commands:
+  print            Convert the whole page
+  selector <css>   Convert the elements matching a CSS selector
+  eval <js>        Evaluate JavaScript in the page and print the result
+  reload           Reload the page
+  help             Show this help
+  quit             Close the browser and exit";
+
+/// A command typed into the REPL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Print,
+    Selector(String),
+    Eval(String),
+    Reload,
+    Help,
+    Quit,
+}
+
+impl Command {
+    /// Parse a line of input. Blank lines are `None`.
+    pub fn parse(line: &str) -> Result<Option<Command>, String> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(None);
+        }
+        let (name, arg) = match line.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (line, ""),
+        };
+        let command = match name {
+            "print" => Command::Print,
+            "reload" => Command::Reload,
+            "help" => Command::Help,
+            "quit" | "exit" => Command::Quit,
+            "selector" | "eval" if arg.is_empty() => {
+                return Err(format!("usage: {} <{}>", name, if name == "eval" { "js" } else { "css" }));
+            }
+            "selector" => Command::Selector(arg.to_string()),
+            "eval" => Command::Eval(arg.to_string()),
+            _ => return Err(format!("unknown command `{}`, type `help` for a list", name)),
+        };
+        Ok(Some(command))
+    }
+}
+
+/// Read commands from `input` until it ends or `quit` is typed, running
+/// each with `execute` and writing its output. A failing command is
+/// reported and the REPL carries on.
+pub async fn run<R, W, F, Fut>(input: R, mut output: W, mut execute: F) -> io::Result<()>
+where
+    R: BufRead,
+    W: Write,
+    F: FnMut(Command) -> Fut,
+    Fut: Future<Output = Result<String, HeadfulError>>,
+{
+    writeln!(output, "Type `help` for a list of commands")?;
+    let mut lines = input.lines();
+    loop {
+        write!(output, "> ")?;
+        output.flush()?;
+        let Some(line) = lines.next() else {
+            break;
+        };
+        match Command::parse(&line?) {
+            Ok(None) => {}
+            Ok(Some(Command::Quit)) => break,
+            Ok(Some(Command::Help)) => writeln!(output, "{}", HELP)?,
+            Ok(Some(command)) => match execute(command).await {
+                Ok(text) => writeln!(output, "{}", text)?,
+                Err(e) => writeln!(output, "Error: {}", e)?,
+            },
+            Err(e) => writeln!(output, "Error: {}", e)?,
+        }
+    }
+    writeln!(output)?;
+    Ok(())
+}
+
+/// Run a command against an open page
+pub async fn execute(page: &Page, config: &FetchConfig, command: Command) -> Result<String, HeadfulError> {
+    match command {
+        Command::Print => fetch::live_markdown(page, config).await,
+        Command::Selector(selector) => fetch::live_selector_markdown(page, config, &selector).await,
+        Command::Eval(script) => {
+            let result = page.evaluate(script).await?;
+            Ok(result.value().map(jsonld::display).unwrap_or_else(|| "undefined".to_string()))
+        }
+        Command::Reload => {
+            page.reload().await?;
+            page.wait_for_navigation().await?;
+            let url = page.url().await?.unwrap_or_default();
+            Ok(format!("Reloaded {}", url))
+        }
+        Command::Help | Command::Quit => Ok(String::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(Command::parse("  print "), Ok(Some(Command::Print)));
+        assert_eq!(Command::parse("selector main > h1"), Ok(Some(Command::Selector("main > h1".to_string()))));
+        assert_eq!(Command::parse("eval document.title"), Ok(Some(Command::Eval("document.title".to_string()))));
+        assert_eq!(Command::parse("exit"), Ok(Some(Command::Quit)));
+        assert_eq!(Command::parse(""), Ok(None));
+        assert_eq!(Command::parse("selector"), Err("usage: selector <css>".to_string()));
+        assert!(Command::parse("click a").unwrap_err().starts_with("unknown command `click`"));
+    }
+
+    #[tokio::test]
+    async fn test_scripted_session() {
+        let input = "selector h1\n\neval 1 + 1\nbogus\nselector .missing\nquit\nprint\n";
+        let mut output = Vec::new();
+        let mut executed = Vec::new();
+        run(input.as_bytes(), &mut output, |command| {
+            executed.push(command.clone());
+            async move {
+                match command {
+                    Command::Selector(selector) if selector == "h1" => Ok("# Welcome".to_string()),
+                    Command::Selector(selector) => Err(format!("No element matches {}", selector).into()),
+                    Command::Eval(_) => Ok("2".to_string()),
+                    _ => Ok("page".to_string()),
+                }
+            }
+        })
+        .await
+        .unwrap();
+
+        // Nothing runs after quitting
+        assert_eq!(executed, vec![
+            Command::Selector("h1".to_string()),
+            Command::Eval("1 + 1".to_string()),
+            Command::Selector(".missing".to_string()),
+        ]);
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output,
+            "Type `help` for a list of commands\n\
+             > # Welcome\n\
+             > > 2\n\
+             > Error: unknown command `bogus`, type `help` for a list\n\
+             > Error: No element matches .missing\n\
+             > \n"
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Writer state guarded by the sink's lock
+struct State<W> {
+    writer: W,
+    /// Index of the next record to write
+    next: usize,
+    /// Records that finished before the ones ahead of them
+    pending: BTreeMap<usize, Vec<u8>>,
+}
+
+/// Serializes output from concurrent tasks onto one writer.
+///
+/// Each record is written whole while holding the lock so records never
+/// interleave, and records are written in index order (the order of the
+/// URLs) even when later pages finish first. Clones share the writer.
+pub struct OutputSink<W> {
+    state: Arc<Mutex<State<W>>>,
+}
+
+impl<W> Clone for OutputSink<W> {
+    fn clone(&self) -> Self {
+        OutputSink { state: Arc::clone(&self.state) }
+    }
+}
+
+impl<W: Write> OutputSink<W> {
+    pub fn new(writer: W) -> Self {
+        OutputSink {
+            state: Arc::new(Mutex::new(State { writer, next: 0, pending: BTreeMap::new() })),
+        }
+    }
+
+    /// Write the record at `index` once every earlier record has been
+    /// written, flushing so consumers see it straight away. Each index
+    /// must be submitted exactly once.
+    pub fn submit(&self, index: usize, record: Vec<u8>) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.pending.insert(index, record);
+        let mut wrote = false;
+        loop {
+            let next = state.next;
+            let Some(record) = state.pending.remove(&next) else {
+                break;
+            };
+            state.writer.write_all(&record)?;
+            state.next += 1;
+            wrote = true;
+        }
+        if wrote {
+            state.writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Skip a record that won't be submitted, such as a page that failed,
+    /// so the records after it aren't held back
+    pub fn skip(&self, index: usize) -> io::Result<()> {
+        self.submit(index, Vec::new())
+    }
+
+    /// Take back the writer once all clones are dropped
+    pub fn into_inner(self) -> Option<W> {
+        let state = Arc::try_unwrap(self.state).ok()?;
+        Some(state.into_inner().unwrap_or_else(PoisonError::into_inner).writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_concurrent_records_do_not_interleave() {
+        let sink = OutputSink::new(Vec::new());
+        let threads: Vec<_> = (0..8)
+            .rev()
+            .map(|index| {
+                let sink = sink.clone();
+                std::thread::spawn(move || {
+                    let record: String = (0..200).map(|line| format!("page {} line {}\n", index, line)).collect();
+                    sink.submit(index, record.into_bytes()).unwrap();
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let out = String::from_utf8(sink.into_inner().unwrap()).unwrap();
+        let expected: String = (0..8)
+            .flat_map(|index| (0..200).map(move |line| format!("page {} line {}\n", index, line)))
+            .collect();
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_skipped_record_releases_later_ones() {
+        let sink = OutputSink::new(Vec::new());
+        sink.submit(1, b"second\n".to_vec()).unwrap();
+        sink.skip(0).unwrap();
+        assert_eq!(sink.into_inner().unwrap(), b"second\n");
+    }
+}
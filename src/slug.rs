@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+
+use clap::ValueEnum;
+
+/// Anchor algorithm of the renderer the markdown is written for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SlugStyle {
+    /// GitHub: keeps unicode letters and every hyphen, so `a - b` becomes
+    /// `a---b`
+    #[default]
+    Github,
+    /// GitLab: like GitHub but runs of hyphens collapse to one
+    Gitlab,
+    /// Letters and digits joined by single hyphens, with no hyphens at
+    /// either end
+    Plain,
+}
+
+/// Turn heading text into an anchor using the given style
+pub fn slugify(text: &str, style: SlugStyle) -> String {
+    let text = text.trim().to_lowercase();
+    match style {
+        SlugStyle::Github => github(&text),
+        SlugStyle::Gitlab => collapse_hyphens(&github(&text)),
+        SlugStyle::Plain => {
+            let words: String = text.chars()
+                .map(|c| if c.is_alphanumeric() { c } else { '-' })
+                .collect();
+            collapse_hyphens(&words).trim_matches('-').to_string()
+        }
+    }
+}
+
+/// Drop punctuation other than `-` and `_` and replace spaces with `-`
+fn github(text: &str) -> String {
+    text.chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            '-' | '_' => Some(c),
+            c if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Replace each run of hyphens with a single hyphen
+fn collapse_hyphens(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c != '-' || !collapsed.ends_with('-') {
+            collapsed.push(c);
+        }
+    }
+    collapsed
+}
+
+/// Generates unique anchors for the headings of a document, suffixing
+/// repeats with `-1`, `-2` and so on
+#[derive(Debug, Default)]
+pub struct Slugger {
+    style: SlugStyle,
+    seen: HashMap<String, usize>,
+}
+
+impl Slugger {
+    pub fn new(style: SlugStyle) -> Self {
+        Slugger { style, seen: HashMap::new() }
+    }
+
+    /// The anchor for the next heading with this text
+    pub fn slug(&mut self, text: &str) -> String {
+        let slug = slugify(text, self.style);
+        let count = self.seen.entry(slug.clone()).or_insert(0);
+        let unique = if *count == 0 { slug } else { format!("{}-{}", slug, count) };
+        *count += 1;
+        unique
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slug_styles() {
+        // Each heading gets a different slug in each style
+        let cases = [
+            ("Hello, World! -- Über_cool", ["hello-world----über_cool", "hello-world-über_cool", "hello-world-über-cool"]),
+            ("a - b", ["a---b", "a-b", "a-b"]),
+            ("snake_case", ["snake_case", "snake_case", "snake-case"]),
+            ("-- Intro --", ["---intro---", "-intro-", "intro"]),
+        ];
+        for (heading, [github, gitlab, plain]) in cases {
+            assert_eq!(slugify(heading, SlugStyle::Github), github, "{}", heading);
+            assert_eq!(slugify(heading, SlugStyle::Gitlab), gitlab, "{}", heading);
+            assert_eq!(slugify(heading, SlugStyle::Plain), plain, "{}", heading);
+        }
+    }
+
+    #[test]
+    fn test_duplicate_headings() {
+        let mut slugger = Slugger::new(SlugStyle::Github);
+        assert_eq!(slugger.slug("Usage"), "usage");
+        assert_eq!(slugger.slug("Usage"), "usage-1");
+        assert_eq!(slugger.slug("Usage"), "usage-2");
+    }
+}
@@ -0,0 +1,44 @@
+/// Count the words in converted markdown, skipping link targets and
+/// tokens that are only markdown syntax such as `#`, `-` or `|`
+pub fn word_count(markdown: &str) -> usize {
+    let mut text = String::with_capacity(markdown.len());
+    let mut rest = markdown;
+    while let Some(start) = rest.find("](") {
+        text.push_str(&rest[..start]);
+        rest = match rest[start..].find(')') {
+            Some(end) => &rest[start + end + 1..],
+            None => "",
+        };
+    }
+    text.push_str(rest);
+
+    text.split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .count()
+}
+
+/// Estimated minutes to read a number of words at the given pace,
+/// rounded up so any content takes at least a minute
+pub fn reading_time_minutes(words: usize, words_per_minute: usize) -> usize {
+    words.div_ceil(words_per_minute.max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_word_count() {
+        let md = "# Hello world\n\n- one [two](https://example.com/a b) three\n\n| --- |\n\n---";
+        assert_eq!(word_count(md), 5);
+    }
+
+    #[test]
+    fn test_reading_time_at_200_wpm() {
+        let md = vec!["word"; 1000].join(" ");
+        assert_eq!(reading_time_minutes(word_count(&md), 200), 5);
+        assert_eq!(reading_time_minutes(1001, 200), 6);
+        assert_eq!(reading_time_minutes(1, 200), 1);
+        assert_eq!(reading_time_minutes(0, 200), 0);
+    }
+}
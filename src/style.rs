@@ -0,0 +1,111 @@
+use clap::ValueEnum;
+
+/// Markers used for emphasis (italic) and strong (bold) text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum EmphasisStyle {
+    /// `*italic*` and `**bold**`
+    #[default]
+    Asterisk,
+    /// `_italic_` and `__bold__`
+    Underscore,
+}
+
+impl EmphasisStyle {
+    /// Marker wrapping italic text
+    pub fn italic(&self) -> &'static str {
+        match self {
+            EmphasisStyle::Asterisk => "*",
+            EmphasisStyle::Underscore => "_",
+        }
+    }
+
+    /// Marker wrapping bold text
+    pub fn bold(&self) -> &'static str {
+        match self {
+            EmphasisStyle::Asterisk => "**",
+            EmphasisStyle::Underscore => "__",
+        }
+    }
+}
+
+/// Markup for inserted (`<ins>`) text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum InsertionStyle {
+    /// `++inserted++`, understood by markdown-it and some other renderers
+    #[default]
+    Plus,
+    /// `<ins>inserted</ins>`, rendered as underlined text where HTML is
+    /// allowed
+    Html,
+}
+
+impl InsertionStyle {
+    /// Markers placed before and after inserted text
+    pub fn markers(&self) -> (&'static str, &'static str) {
+        match self {
+            InsertionStyle::Plus => ("++", "++"),
+            InsertionStyle::Html => ("<ins>", "</ins>"),
+        }
+    }
+}
+
+/// Characters used for horizontal rules
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum HrStyle {
+    /// `---`
+    #[default]
+    Dashes,
+    /// `***`
+    Asterisks,
+    /// `___`
+    Underscores,
+}
+
+impl HrStyle {
+    /// The horizontal rule line
+    pub fn rule(&self) -> &'static str {
+        match self {
+            HrStyle::Dashes => "---",
+            HrStyle::Asterisks => "***",
+            HrStyle::Underscores => "___",
+        }
+    }
+}
+
+/// How runs of adjacent sibling links are laid out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LinkRunStyle {
+    /// On one line separated by spaces
+    #[default]
+    Inline,
+    /// As a bulleted list, one link per item
+    Bullets,
+}
+
+/// Wrap text in an emphasis marker, keeping surrounding whitespace
+/// outside the markers so the result stays valid markdown
+pub fn wrap_emphasis(text: &str, marker: &str) -> String {
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        return text.to_string();
+    }
+    let leading = &text[..text.len() - text.trim_start().len()];
+    let trailing = &text[text.trim_end().len()..];
+    format!("{}{}{}{}{}", leading, marker, trimmed, marker, trailing)
+}
+
+/// Characters that markdown renderers may interpret inside prose
+const MARKDOWN_SPECIAL: &[char] = &['\\', '`', '*', '_', '[', ']', '#', '<', '>'];
+
+/// Backslash-escape markdown-significant characters so text renders
+/// literally
+pub fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if MARKDOWN_SPECIAL.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
@@ -0,0 +1,43 @@
+/// Fill the `{{name}}` placeholders in a template with values. Spaces
+/// inside the braces are ignored. A placeholder without a value or left
+/// unclosed is an error rather than being printed as is.
+pub fn render(template: &str, values: &[(&str, &str)]) -> Result<String, String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after.find("}}").ok_or_else(|| "unclosed `{{` in template".to_string())?;
+        let name = after[..end].trim();
+        let value = values.iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| *value)
+            .ok_or_else(|| format!("undefined placeholder `{{{{{}}}}}` in template", name))?;
+        rendered.push_str(value);
+        rest = &after[end + 2..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let template = "<article>\n<h1>{{ title }}</h1>\n\n{{markdown}}\n</article>\n";
+        let values = [("title", "Example Domain"), ("markdown", "This domain is for use in examples.")];
+        assert_eq!(
+            render(template, &values).unwrap(),
+            "<article>\n<h1>Example Domain</h1>\n\nThis domain is for use in examples.\n</article>\n"
+        );
+    }
+
+    #[test]
+    fn test_undefined_placeholder() {
+        let err = render("{{markdown}} by {{author}}", &[("markdown", "Hi")]).unwrap_err();
+        assert_eq!(err, "undefined placeholder `{{author}}` in template");
+        assert!(render("{{markdown", &[("markdown", "Hi")]).is_err());
+    }
+}
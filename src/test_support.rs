@@ -0,0 +1,125 @@
+//! Builders for constructing synthetic accessibility trees in tests
+
+use crate::axtree::{
+    AxNode, AxTree, IgnoredReason, Name, Property, PropertyValue, PropertyValueContent, Role,
+    RoleValueContent, STATIC_TEXT_ROLE, ValueBool,
+};
+
+/// Builds an `AxTree` rooted at a `RootWebArea` node with ID "1".
+///
+/// Each method that adds a node makes it the current node so that
+/// property setters like `name` and `int_prop` apply to it.
+pub struct AxTreeBuilder {
+    nodes: Vec<AxNode>,
+}
+
+#[allow(dead_code)]
+impl AxTreeBuilder {
+    pub fn new() -> Self {
+        AxTreeBuilder {
+            nodes: vec![new_node("1", None, RoleValueContent::Named("RootWebArea".to_string()))],
+        }
+    }
+
+    /// Add a node with a named role as the last child of `parent`
+    pub fn node(self, id: &str, role: &str, parent: &str) -> Self {
+        self.push(new_node(id, Some(parent), RoleValueContent::Named(role.to_string())))
+    }
+
+    /// Add a StaticText node with the given text as the last child of
+    /// `parent`
+    pub fn text(self, id: &str, text: &str, parent: &str) -> Self {
+        let mut node = new_node(id, Some(parent), RoleValueContent::Internal(STATIC_TEXT_ROLE));
+        node.role.role_type = "internalRole".to_string();
+        node.name = Some(computed_name(text));
+        self.push(node)
+    }
+
+    /// Set the accessible name of the current node
+    pub fn name(mut self, value: &str) -> Self {
+        self.current().name = Some(computed_name(value));
+        self
+    }
+
+    /// Add an integer property to the current node
+    pub fn int_prop(self, name: &str, value: i64) -> Self {
+        self.prop(name, "integer", PropertyValueContent::Integer(value))
+    }
+
+    /// Add a boolean property to the current node
+    pub fn bool_prop(self, name: &str, value: bool) -> Self {
+        self.prop(name, "boolean", PropertyValueContent::SimpleBoolean(value))
+    }
+
+    /// Add a string property to the current node
+    pub fn str_prop(self, name: &str, value: &str) -> Self {
+        self.prop(name, "string", PropertyValueContent::String(value.to_string()))
+    }
+
+    /// Set the backend DOM node ID of the current node
+    pub fn backend_id(mut self, id: i64) -> Self {
+        self.current().backend_dom_node_id = Some(id);
+        self
+    }
+
+    /// Mark the current node as ignored for the given reason
+    pub fn ignored(mut self, reason: &str) -> Self {
+        let node = self.current();
+        node.ignored = true;
+        node.ignored_reasons.get_or_insert_with(Vec::new).push(IgnoredReason {
+            name: reason.to_string(),
+            value_type: ValueBool { value_type: "boolean".to_string(), value: true },
+        });
+        self
+    }
+
+    pub fn build(self) -> AxTree {
+        AxTree { nodes: self.nodes }
+    }
+
+    fn push(mut self, node: AxNode) -> Self {
+        if let Some(parent_id) = node.parent_id.clone() {
+            let parent = self.nodes.iter_mut()
+                .find(|n| n.node_id == parent_id)
+                .unwrap_or_else(|| panic!("parent node {} not found", parent_id));
+            parent.child_ids.get_or_insert_with(Vec::new).push(node.node_id.clone());
+        }
+        self.nodes.push(node);
+        self
+    }
+
+    fn prop(mut self, name: &str, value_type: &str, value: PropertyValueContent) -> Self {
+        self.current().properties.get_or_insert_with(Vec::new).push(Property {
+            name: name.to_string(),
+            value_type: PropertyValue { value_type: value_type.to_string(), value },
+        });
+        self
+    }
+
+    fn current(&mut self) -> &mut AxNode {
+        self.nodes.last_mut().expect("builder always has a root node")
+    }
+}
+
+fn new_node(id: &str, parent: Option<&str>, role: RoleValueContent) -> AxNode {
+    AxNode {
+        backend_dom_node_id: None,
+        child_ids: None,
+        chrome_role: None,
+        ignored: false,
+        ignored_reasons: None,
+        node_id: id.to_string(),
+        parent_id: parent.map(String::from),
+        role: Role { role_type: "role".to_string(), value: role },
+        name: None,
+        properties: None,
+    }
+}
+
+fn computed_name(value: &str) -> Name {
+    Name {
+        sources: Vec::new(),
+        name_type: "computedString".to_string(),
+        value: value.to_string(),
+    }
+}